@@ -12,6 +12,10 @@ pub enum PSMPacketC2S {
     },
     /// Tablet movement!
     TabletEvent {
+        /// Zero-based index of the tablet, packets only go to contexts opened on it.
+        #[serde(default)]
+        device: u32,
+        /// Only TPS_PROXIMITY and TPS_INVERT are taken from the client, the rest is computed by PSM.
         status: u32,
         buttons: u32,
         x: u32,
@@ -19,11 +23,42 @@ pub enum PSMPacketC2S {
         z: u32,
         normal_pressure: u32,
         tangential_pressure: u32,
+        /// Is the stylus flipped to the eraser?
+        #[serde(default)]
+        eraser: bool,
+        /// When the client sampled the event, in milliseconds from any epoch.
+        /// Used for pkTime if `align_client_time` is on in psm.json.
+        #[serde(default)]
+        time: Option<u64>,
     },
     /// Is stylus in proximity?
     Proximity {
         value: bool,
     },
+    /// ExpressKey press or release
+    ExpressKey {
+        /// Zero-based index of the key.
+        control: u8,
+        pressed: bool,
+    },
+    /// Touch strip movement
+    TouchStrip {
+        /// Zero-based index of the strip.
+        control: u8,
+        /// Zero-based index of the strip's current mode.
+        mode: u8,
+        /// Touch position, 0 when the finger is lifted.
+        position: u32,
+    },
+    /// Touch ring (dial) movement
+    TouchRing {
+        /// Zero-based index of the ring.
+        control: u8,
+        /// Zero-based index of the ring's current mode.
+        mode: u8,
+        /// Touch position, 0 when the finger is lifted.
+        position: u32,
+    },
     /// Set context options
     ConfigureContext {
         /// Returns the status.
@@ -66,9 +101,15 @@ pub enum PSMPacketC2S {
         sys_ext_x: i32,
         /// Returns the current screen display size in pixels. (Y)
         sys_ext_y: i32,
+        /// What happens to points outside of the contexts' output area, unchanged if missing.
+        #[serde(default)]
+        out_of_area: Option<OutOfAreaPolicy>,
     },
     /// Set device options
     ConfigureDevice {
+        /// Zero-based index of the tablet to configure.
+        #[serde(default)]
+        device: u32,
         /// Returns flags indicating hardware and driver capabilities, as defined below:
         /// HWC_INTEGRATED: Indicates that the display and digitizer share the same surface.
         /// HWC_TOUCH: Indicates that the cursor must be in physical contact with the device to report position.
@@ -101,12 +142,35 @@ pub enum PSMPacketC2S {
         /// 3-element array describing the tablet's rotation range and resolution capabilities.
         rotation: [Axis; 3],
     },
+    /// Change the strings apps identify the tablet by, missing ones are unchanged.
+    ConfigureIdentity {
+        /// Tablet hardware identification string (IFC_WINTABID).
+        #[serde(default)]
+        wintab_id: Option<String>,
+        /// Zero-based index of the tablet the device strings are for.
+        #[serde(default)]
+        device: u32,
+        /// Displayed name of the device (DVC_NAME).
+        #[serde(default)]
+        device_name: Option<String>,
+        /// Plug and Play ID of the device (DVC_PNPID).
+        #[serde(default)]
+        pnp_id: Option<String>,
+        /// Names of the device's cursor types (CSR_NAME), in order.
+        #[serde(default)]
+        cursor_names: Option<Vec<String>>,
+    },
+    /// Switch to a built-in tablet profile, e.g. "intuos-pro-m".
+    /// Replaces the main device's descriptors like `profile` in psm.json.
+    SelectProfile {
+        name: String,
+    },
     Debug {
         msg: String,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum PSMPacketS2C {
     /// Server's response to [PSMPacketC2S::Hi]
@@ -114,6 +178,24 @@ pub enum PSMPacketS2C {
         /// Server compatible version (should be [`COMPATIBLE_VERSION`])
         compatible: u32,
     },
+    /// A context was closed by PSM because its window was destroyed without WTClose.
+    ContextReaped {
+        /// Handle of the closed context.
+        handle: u64,
+    },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// What happens to pen packets outside of a context's output area.
+pub enum OutOfAreaPolicy {
+    /// Throw the packet away.
+    Drop,
+    /// Move the point to the nearest edge of the area.
+    #[default]
+    Clamp,
+    /// Deliver the point as is.
+    Pass,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -15,6 +15,15 @@ struct Args {
     z: u32,
     normal_pressure: u32,
     tangential_pressure: u32,
+    /// Send the packets as the eraser.
+    #[arg(long)]
+    eraser: bool,
+    /// Zero-based index of the tablet the packets come from.
+    #[arg(long, default_value_t = 0)]
+    device: u32,
+    /// Switch PSM to a built-in tablet profile first, e.g. "intuos-pro-m".
+    #[arg(long)]
+    profile: Option<String>,
 }
 
 fn main() {
@@ -35,10 +44,14 @@ fn fmain() -> color_eyre::Result<()> {
             name: "test_client 0.1.0".to_string(),
         },
     )?;
+    if let Some(name) = args.profile {
+        send_packet(&mut stream, &PSMPacketC2S::SelectProfile { name })?;
+    }
     send_packet(&mut stream, &PSMPacketC2S::Proximity { value: true })?;
     send_packet(
         &mut stream,
         &PSMPacketC2S::TabletEvent {
+            device: args.device,
             status: args.status,
             buttons: args.buttons,
             x: args.x,
@@ -46,6 +59,8 @@ fn fmain() -> color_eyre::Result<()> {
             z: args.z,
             normal_pressure: args.normal_pressure,
             tangential_pressure: args.tangential_pressure,
+            eraser: args.eraser,
+            time: None,
         },
     )?;
     std::thread::sleep(std::time::Duration::from_millis(50));
@@ -54,6 +69,7 @@ fn fmain() -> color_eyre::Result<()> {
             send_packet(
                 &mut stream,
                 &PSMPacketC2S::TabletEvent {
+                    device: args.device,
                     status: args.status,
                     buttons: args.buttons,
                     x: args.x + i * 50,
@@ -61,6 +77,8 @@ fn fmain() -> color_eyre::Result<()> {
                     z: args.z,
                     normal_pressure: args.normal_pressure,
                     tangential_pressure: args.tangential_pressure,
+                    eraser: args.eraser,
+                    time: None,
                 },
             )?;
             std::thread::sleep(std::time::Duration::from_millis(100));
@@ -69,6 +87,7 @@ fn fmain() -> color_eyre::Result<()> {
     send_packet(
         &mut stream,
         &PSMPacketC2S::TabletEvent {
+            device: args.device,
             status: args.status,
             buttons: 0,
             x: args.x,
@@ -76,12 +95,15 @@ fn fmain() -> color_eyre::Result<()> {
             z: args.z,
             normal_pressure: 0,
             tangential_pressure: args.tangential_pressure,
+            eraser: args.eraser,
+            time: None,
         },
     )?;
     std::thread::sleep(std::time::Duration::from_millis(300));
     send_packet(
         &mut stream,
         &PSMPacketC2S::TabletEvent {
+            device: args.device,
             status: args.status,
             buttons: 0,
             x: args.x,
@@ -89,6 +111,8 @@ fn fmain() -> color_eyre::Result<()> {
             z: 1020,
             normal_pressure: 0,
             tangential_pressure: args.tangential_pressure,
+            eraser: args.eraser,
+            time: None,
         },
     )?;
     std::thread::sleep(std::time::Duration::from_millis(300));
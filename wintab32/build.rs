@@ -0,0 +1,18 @@
+use std::{env, path::PathBuf};
+
+/// Links with wintab32.def, so exports keep the ordinals of the original wintab32.dll.
+/// The same file works for x86 and x64, the linkers add the x86 underscore themselves.
+/// It is passed after the .def rustc generates for the cdylib, tests/exports.rs checks that it wins.
+fn main() {
+    println!("cargo:rerun-if-changed=wintab32.def");
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return;
+    }
+    let def = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("wintab32.def");
+    if env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc") {
+        println!("cargo:rustc-cdylib-link-arg=/DEF:{}", def.display());
+    } else {
+        // GNU ld takes module-definition files as inputs
+        println!("cargo:rustc-cdylib-link-arg={}", def.display());
+    }
+}
@@ -0,0 +1,238 @@
+use psm_common::netcode::OutOfAreaPolicy;
+
+use crate::ffi::{CXO_MARGIN, CXO_MGNINSIDE, Packet, TPS_MARGIN, WtiDevice, WtiLogicalContext};
+
+/// One axis of a context's output area.
+struct Span {
+    min: i32,
+    max: i32,
+    margin: i32,
+}
+impl Span {
+    fn new(org: i32, ext: i32, margin: i32) -> Self {
+        Self {
+            min: org,
+            max: org + ext.abs(),
+            margin,
+        }
+    }
+
+    fn contains(&self, value: i32) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+
+    /// In the band of `margin` around the edges, inside or outside of the area.
+    fn in_margin(&self, value: i32, inside: bool) -> bool {
+        if inside {
+            self.contains(value)
+                && (value < self.min + self.margin || value > self.max - self.margin)
+        } else {
+            !self.contains(value)
+                && (self.min - self.margin..=self.max + self.margin).contains(&value)
+        }
+    }
+
+    fn clamp(&self, value: i32) -> i32 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+/// The device's margins, scaled from tablet to output coordinates.
+pub fn margins(lc: &WtiLogicalContext, device: &WtiDevice) -> [i32; 3] {
+    let scale = |margin: i32, in_ext: i32, out_ext: i32| {
+        if in_ext == 0 {
+            return 0;
+        }
+        (margin as i64 * out_ext.abs() as i64 / in_ext.abs() as i64) as i32
+    };
+    [
+        scale(device.x_margin, lc.in_ext_x, lc.out_ext_x),
+        scale(device.y_margin, lc.in_ext_y, lc.out_ext_y),
+        scale(device.z_margin, lc.in_ext_z, lc.out_ext_z),
+    ]
+}
+
+/// Applies the context's margins (CXO_MARGIN, CXO_MGNINSIDE) to a packet, setting TPS_MARGIN,
+/// and handles points outside of the output area according to `policy`.
+/// Returns false if the packet should be dropped.
+///
+/// Inside margins are a band along the edges of the area, points in it are reported as is.
+/// Outside margins extend the area, points in them are clamped to its edges.
+pub fn fit_packet(
+    lc: &WtiLogicalContext,
+    margins: [i32; 3],
+    policy: OutOfAreaPolicy,
+    packet: &mut Packet,
+) -> bool {
+    let spans = [
+        Span::new(lc.out_org_x, lc.out_ext_x, margins[0]),
+        Span::new(lc.out_org_y, lc.out_ext_y, margins[1]),
+        Span::new(lc.out_org_z, lc.out_ext_z, margins[2]),
+    ];
+    // contexts without a Z axis don't limit it
+    let spans = if lc.out_ext_z == 0 {
+        &spans[..2]
+    } else {
+        &spans[..]
+    };
+    let mut coords = [packet.x as i32, packet.y as i32, packet.z as i32];
+    let clamp = |coords: &mut [i32; 3]| {
+        for (value, span) in coords.iter_mut().zip(spans) {
+            *value = span.clamp(*value);
+        }
+    };
+    let in_area = |coords: &[i32; 3], grow: bool| {
+        spans
+            .iter()
+            .zip(coords)
+            .all(|(span, x)| span.contains(*x) || (grow && span.in_margin(*x, false)))
+    };
+
+    if lc.options & CXO_MARGIN != 0 {
+        let inside = lc.options & CXO_MGNINSIDE != 0;
+        let in_margin = spans
+            .iter()
+            .zip(&coords)
+            .any(|(span, x)| span.in_margin(*x, inside));
+        if in_margin && in_area(&coords, !inside) {
+            packet.status |= TPS_MARGIN;
+            clamp(&mut coords);
+        }
+    }
+
+    if !in_area(&coords, false) {
+        match policy {
+            OutOfAreaPolicy::Drop => return false,
+            OutOfAreaPolicy::Clamp => clamp(&mut coords),
+            OutOfAreaPolicy::Pass => {}
+        }
+    }
+
+    packet.x = coords[0] as u32;
+    packet.y = coords[1] as u32;
+    packet.z = coords[2] as u32;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1000x1000 output area at (0, 0), without a Z axis.
+    fn context(options: u32) -> WtiLogicalContext {
+        WtiLogicalContext {
+            options,
+            in_ext_x: 2000,
+            in_ext_y: 2000,
+            in_ext_z: 0,
+            out_org_x: 0,
+            out_org_y: 0,
+            out_org_z: 0,
+            out_ext_x: 1000,
+            out_ext_y: -1000,
+            out_ext_z: 0,
+            ..WtiLogicalContext::psm_default()
+        }
+    }
+
+    const MARGINS: [i32; 3] = [50, 50, 0];
+
+    /// The fitted (x, y, TPS_MARGIN) of a point, or `None` if it's dropped.
+    fn fit(options: u32, policy: OutOfAreaPolicy, x: i32, y: i32) -> Option<(i32, i32, bool)> {
+        let mut packet = Packet {
+            x: x as u32,
+            y: y as u32,
+            z: 5000,
+            ..Default::default()
+        };
+        if !fit_packet(&context(options), MARGINS, policy, &mut packet) {
+            return None;
+        }
+        // contexts without a Z axis don't limit it
+        assert_eq!(packet.z, 5000);
+        let margin = packet.status & TPS_MARGIN != 0;
+        Some((packet.x as i32, packet.y as i32, margin))
+    }
+
+    #[test]
+    fn margins_are_scaled_to_output() {
+        let device = WtiDevice {
+            x_margin: 100,
+            y_margin: 40,
+            z_margin: 7,
+            ..WtiDevice::psm_default()
+        };
+        // the Y extent is flipped, the Z axis has no input extent
+        assert_eq!(margins(&context(0), &device), [50, 20, 0]);
+    }
+
+    #[test]
+    fn points_inside_pass_through() {
+        for options in [0, CXO_MARGIN, CXO_MARGIN | CXO_MGNINSIDE] {
+            for policy in [
+                OutOfAreaPolicy::Drop,
+                OutOfAreaPolicy::Clamp,
+                OutOfAreaPolicy::Pass,
+            ] {
+                assert_eq!(fit(options, policy, 500, 500), Some((500, 500, false)));
+                assert_eq!(fit(options, policy, 100, 900), Some((100, 900, false)));
+            }
+        }
+    }
+
+    #[test]
+    fn points_outside_follow_policy() {
+        for options in [0, CXO_MARGIN, CXO_MARGIN | CXO_MGNINSIDE] {
+            assert_eq!(fit(options, OutOfAreaPolicy::Drop, 1100, 500), None);
+            assert_eq!(
+                fit(options, OutOfAreaPolicy::Clamp, 1100, -80),
+                Some((1000, 0, false))
+            );
+            assert_eq!(
+                fit(options, OutOfAreaPolicy::Pass, 1100, -80),
+                Some((1100, -80, false))
+            );
+        }
+    }
+
+    #[test]
+    fn outside_margins_extend_the_area() {
+        // without CXO_MARGIN the band is just outside of the area
+        assert_eq!(fit(0, OutOfAreaPolicy::Drop, 1030, 500), None);
+        assert_eq!(
+            fit(0, OutOfAreaPolicy::Pass, 1030, 500),
+            Some((1030, 500, false))
+        );
+        for policy in [
+            OutOfAreaPolicy::Drop,
+            OutOfAreaPolicy::Clamp,
+            OutOfAreaPolicy::Pass,
+        ] {
+            assert_eq!(fit(CXO_MARGIN, policy, 1030, -20), Some((1000, 0, true)));
+        }
+        // the edge of the area isn't in the margin
+        assert_eq!(
+            fit(CXO_MARGIN, OutOfAreaPolicy::Drop, 1000, 500),
+            Some((1000, 500, false))
+        );
+    }
+
+    #[test]
+    fn inside_margins_are_a_band_along_the_edges() {
+        let options = CXO_MARGIN | CXO_MGNINSIDE;
+        assert_eq!(
+            fit(options, OutOfAreaPolicy::Drop, 980, 500),
+            Some((980, 500, true))
+        );
+        assert_eq!(
+            fit(options, OutOfAreaPolicy::Drop, 500, 20),
+            Some((500, 20, true))
+        );
+        // inside margins don't extend the area
+        assert_eq!(fit(options, OutOfAreaPolicy::Drop, 1030, 500), None);
+        assert_eq!(
+            fit(options, OutOfAreaPolicy::Clamp, 1030, 500),
+            Some((1000, 500, false))
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use windows::{
+    Win32::Globalization::{
+        CP_ACP, MULTI_BYTE_TO_WIDE_CHAR_FLAGS, MultiByteToWideChar, WideCharToMultiByte,
+    },
+    core::PCSTR,
+};
+
+/// String encoding of the caller, picked by the A or W suffix of the function.
+/// Functions without a suffix are treated as W.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// The process' ANSI code page.
+    Ansi,
+    /// UTF-16.
+    Wide,
+}
+impl Charset {
+    /// Size of one character unit, in bytes.
+    pub const fn unit_size(self) -> usize {
+        match self {
+            Charset::Ansi => 1,
+            Charset::Wide => 2,
+        }
+    }
+
+    /// Encodes `value` without a null terminator.
+    pub fn encode(self, value: &str) -> Vec<u8> {
+        match self {
+            Charset::Ansi => to_ansi(value),
+            Charset::Wide => value.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        }
+    }
+
+    /// Encodes `value` with a null terminator.
+    pub fn encode_str(self, value: &str) -> Vec<u8> {
+        let mut out = self.encode(value);
+        out.resize(out.len() + self.unit_size(), 0);
+        out
+    }
+
+    /// Encodes `value` into a zero-padded array of `len` characters, like `lcName`.
+    /// Whole characters are cut off the end to keep room for the null terminator.
+    pub fn encode_fixed(self, value: &str, len: usize) -> Vec<u8> {
+        let max = (len - 1) * self.unit_size();
+        let mut chars = value.chars();
+        let mut out = self.encode(chars.as_str());
+        while out.len() > max {
+            chars.next_back();
+            out = self.encode(chars.as_str());
+        }
+        out.resize(len * self.unit_size(), 0);
+        out
+    }
+
+    /// Encodes `values` as a list of null-terminated strings ended by an extra null, like `CSR_BTNNAMES`.
+    /// An empty list is two nulls.
+    pub fn encode_list(self, values: &[String]) -> Vec<u8> {
+        let mut out = values
+            .iter()
+            .flat_map(|x| self.encode_str(x))
+            .collect::<Vec<_>>();
+        let nulls = if values.is_empty() { 2 } else { 1 };
+        out.resize(out.len() + nulls * self.unit_size(), 0);
+        out
+    }
+
+    /// Decodes a string up to its null terminator or the end of `data`.
+    pub fn decode(self, data: &[u8]) -> String {
+        match self {
+            Charset::Ansi => {
+                let len = data.iter().position(|x| *x == 0).unwrap_or(data.len());
+                from_ansi(&data[..len])
+            }
+            Charset::Wide => {
+                let wide = data
+                    .chunks_exact(2)
+                    .map(|x| u16::from_le_bytes([x[0], x[1]]))
+                    .take_while(|x| *x != 0)
+                    .collect::<Vec<_>>();
+                String::from_utf16_lossy(&wide)
+            }
+        }
+    }
+}
+
+fn to_ansi(value: &str) -> Vec<u8> {
+    let wide = value.encode_utf16().collect::<Vec<_>>();
+    if wide.is_empty() {
+        return Vec::new();
+    }
+    unsafe {
+        let len = WideCharToMultiByte(CP_ACP, 0, &wide, None, PCSTR::null(), None);
+        let mut out = vec![0u8; len.max(0) as usize];
+        let len = WideCharToMultiByte(CP_ACP, 0, &wide, Some(&mut out), PCSTR::null(), None);
+        out.truncate(len.max(0) as usize);
+        out
+    }
+}
+
+fn from_ansi(data: &[u8]) -> String {
+    if data.is_empty() {
+        return String::new();
+    }
+    let flags = MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0);
+    unsafe {
+        let len = MultiByteToWideChar(CP_ACP, flags, data, None);
+        let mut wide = vec![0u16; len.max(0) as usize];
+        let len = MultiByteToWideChar(CP_ACP, flags, data, Some(&mut wide));
+        wide.truncate(len.max(0) as usize);
+        String::from_utf16_lossy(&wide)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_null_terminated() {
+        assert_eq!(Charset::Ansi.encode_str("PSM"), b"PSM\0");
+        assert_eq!(Charset::Wide.encode_str("PSM"), b"P\0S\0M\0\0\0");
+    }
+
+    #[test]
+    fn lists_end_with_two_nulls() {
+        let names = ["A".to_string(), "B".to_string()];
+        assert_eq!(Charset::Ansi.encode_list(&names), b"A\0B\0\0");
+        assert_eq!(Charset::Wide.encode_list(&names), b"A\0\0\0B\0\0\0\0\0");
+        assert_eq!(Charset::Ansi.encode_list(&[]), b"\0\0");
+    }
+
+    #[test]
+    fn fixed_strings_are_padded_and_cut() {
+        assert_eq!(Charset::Ansi.encode_fixed("LOGCTX", 8), b"LOGCTX\0\0");
+        assert_eq!(Charset::Ansi.encode_fixed("LOGCONTEXT", 8), b"LOGCONT\0");
+        let wide = Charset::Wide.encode_fixed("LOGCONTEXT", 8);
+        assert_eq!(wide.len(), 16);
+        assert_eq!(Charset::Wide.decode(&wide), "LOGCONT");
+        // a surrogate pair is never split
+        let wide = Charset::Wide.encode_fixed("ab\u{1f58c}", 4);
+        assert_eq!(Charset::Wide.decode(&wide), "ab");
+    }
+
+    #[test]
+    fn decode_stops_at_null() {
+        assert_eq!(Charset::Ansi.decode(b"Paint\0garbage"), "Paint");
+        assert_eq!(Charset::Wide.decode(b"P\0S\0\0\0M\0"), "PS");
+        assert_eq!(Charset::Wide.decode(b"P\0S\0"), "PS");
+    }
+}
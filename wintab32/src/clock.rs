@@ -0,0 +1,92 @@
+use windows::Win32::System::SystemInformation::GetTickCount;
+
+/// How far an aligned client timestamp may drift from the system clock before re-syncing, in ms.
+const MAX_DRIFT: u32 = 100;
+
+/// Milliseconds on the same clock as GetTickCount and GetMessageTime, wrapping around.
+pub trait TimeSource {
+    fn ticks(&self) -> u32;
+}
+
+pub struct SystemTicks;
+impl TimeSource for SystemTicks {
+    fn ticks(&self) -> u32 {
+        unsafe { GetTickCount() }
+    }
+}
+
+/// Stamps packets with tick time, optionally keeping the spacing of the client's own timestamps.
+///
+/// Client timestamps are in milliseconds from any epoch. They're shifted onto the system clock,
+/// and shifted again whenever they drift more than [MAX_DRIFT] from it or run ahead of it.
+#[derive(Default)]
+pub struct PacketClock {
+    /// Added to client timestamps to get tick time.
+    offset: Option<u32>,
+}
+impl PacketClock {
+    pub fn stamp(&mut self, client_time: Option<u64>, source: &impl TimeSource) -> u32 {
+        let now = source.ticks();
+        let Some(client_time) = client_time.map(|x| x as u32) else {
+            return now;
+        };
+        let aligned = self.offset.map(|x| client_time.wrapping_add(x));
+        match aligned {
+            // ahead of the clock shows up as a huge wrapped difference
+            Some(aligned) if now.wrapping_sub(aligned) <= MAX_DRIFT => aligned,
+            _ => {
+                self.offset = Some(now.wrapping_sub(client_time));
+                now
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    struct MockTicks(Cell<u32>);
+    impl TimeSource for MockTicks {
+        fn ticks(&self) -> u32 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn without_client_time_uses_ticks() {
+        let ticks = MockTicks(Cell::new(5000));
+        let mut clock = PacketClock::default();
+        assert_eq!(clock.stamp(None, &ticks), 5000);
+        ticks.0.set(5020);
+        assert_eq!(clock.stamp(None, &ticks), 5020);
+    }
+
+    #[test]
+    fn client_time_keeps_its_spacing() {
+        let ticks = MockTicks(Cell::new(5000));
+        let mut clock = PacketClock::default();
+        assert_eq!(clock.stamp(Some(1_000_000), &ticks), 5000);
+        // delivered late, but stamped when the client sampled it
+        ticks.0.set(5030);
+        assert_eq!(clock.stamp(Some(1_000_004), &ticks), 5004);
+        assert_eq!(clock.stamp(Some(1_000_008), &ticks), 5008);
+    }
+
+    #[test]
+    fn resyncs_on_drift() {
+        let ticks = MockTicks(Cell::new(u32::MAX - 10));
+        let mut clock = PacketClock::default();
+        assert_eq!(clock.stamp(Some(0), &ticks), u32::MAX - 10);
+        // tick count wraps around
+        ticks.0.set(20);
+        assert_eq!(clock.stamp(Some(25), &ticks), 14);
+        // the client clock ran ahead
+        assert_eq!(clock.stamp(Some(100), &ticks), 20);
+        // and fell behind
+        ticks.0.set(1000);
+        assert_eq!(clock.stamp(Some(200), &ticks), 1000);
+    }
+}
@@ -1,16 +1,110 @@
 use std::io::Read;
 
 use color_eyre::eyre::Context;
-use psm_common::netcode::Axis;
-use serde::{Deserialize, Serialize};
+use psm_common::netcode::{Axis, OutOfAreaPolicy};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    ffi::{
+        SBN_LCLICK, SBN_LDBLCLICK, SBN_LDRAG, SBN_MCLICK, SBN_MDBLCLICK, SBN_MDRAG, SBN_NONE,
+        SBN_RCLICK, SBN_RDBLCLICK, SBN_RDRAG,
+    },
+    throttle::ThrottleMode,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Tablet hardware identification string reported as IFC_WINTABID.
+    #[serde(default = "default_wintab_id")]
+    pub wintab_id: String,
+    /// Name of a built-in tablet profile (see [crate::profile]) replacing
+    /// the identity, axes, cursors and extensions of `preset`.
+    #[serde(default)]
+    pub profile: Option<String>,
     pub preset: TabletPreset,
+    /// More tablets connected next to the one in `preset`, exposed as WTI_DEVICES + 1 and on.
+    #[serde(default)]
+    pub extra_devices: Vec<TabletPreset>,
+    /// Pad controls reported through the Wintab extensions.
+    #[serde(default)]
+    pub extensions: ExtensionsConfig,
+    /// How system contexts (CXO_SYSTEM) move the Windows cursor.
+    #[serde(default)]
+    pub system_cursor: SystemCursorConfig,
+    /// How packets above a context's packet rate are handled.
+    #[serde(default)]
+    pub throttle: ThrottleMode,
+    /// What happens to points outside of a context's output area, unless the client chooses otherwise.
+    #[serde(default)]
+    pub out_of_area: OutOfAreaPolicy,
+    /// Stamp packets with the client's timestamps, shifted onto the system clock,
+    /// instead of the time they were received.
+    #[serde(default)]
+    pub align_client_time: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SystemCursorConfig {
+    /// Move the cursor and click with the pen while a system context is on top.
+    pub enabled: bool,
+    /// Also move the cursor when no context takes the packet (e.g. in dialogs and menus),
+    /// mapped like the default system context.
+    pub without_context: bool,
+}
+impl Default for SystemCursorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            without_context: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtensionsConfig {
+    /// Number of ExpressKeys on the pad.
+    pub express_keys: u32,
+    /// Where the ExpressKeys are: 0 - left, 1 - right, 2 - top, 3 - bottom.
+    pub express_keys_location: u8,
+    /// Touch rings (dials) on the pad.
+    pub touch_rings: Vec<SliderConfig>,
+    /// Touch strips on the pad.
+    pub touch_strips: Vec<SliderConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SliderConfig {
+    /// Number of modes the control can be switched between.
+    pub modes: u32,
+    /// Maximum reported position. Positions start at 0.
+    pub max: u32,
+}
+
+fn default_wintab_id() -> String {
+    "PAIN STUDIO MASK".into()
+}
+
+impl Config {
+    /// Presets of all devices, in WTI_DEVICES order.
+    pub fn device_presets(&self) -> impl Iterator<Item = &TabletPreset> {
+        std::iter::once(&self.preset).chain(self.extra_devices.iter())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TabletPreset {
+    /// Displayed name of the device, "PSMDEV" and its number if missing.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Plug and Play ID of the device reported as DVC_PNPID, e.g. "WACF004".
+    #[serde(default)]
+    pub pnp_id: String,
+    /// Cursor types of the device, exposed as consecutive WTI_CURSORS + n.
+    /// Defaults to a puck, a pressure stylus and its eraser, in the order Wacom drivers use.
+    #[serde(default = "default_cursors")]
+    pub cursors: Vec<CursorConfig>,
     /// Status.
     pub status: u32,
     /// Returns the default context packet report rate, in Hertz.
@@ -79,6 +173,200 @@ pub struct TabletPreset {
     pub rotation: [Axis; 3],
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CursorKind {
+    /// A mouse-like cursor without pressure.
+    Puck,
+    /// A pressure stylus.
+    Pen,
+    /// The inverted end of the pen listed right before it (CRC_INVERT).
+    Eraser,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorConfig {
+    /// Displayed name of the cursor type.
+    pub name: String,
+    pub kind: CursorKind,
+    /// Number of buttons on the cursor.
+    pub buttons: u8,
+    /// Manufacturer-specific ID of the physical cursor, shared by a pen and its eraser.
+    #[serde(default)]
+    pub physical_id: u32,
+    /// Names of the buttons, missing ones are called "Button" and their number.
+    #[serde(default)]
+    pub button_names: Vec<String>,
+    /// Logical button number of each physical button, missing ones map to themselves.
+    #[serde(default, deserialize_with = "deserialize_button_map")]
+    pub button_map: Vec<u8>,
+    /// What each logical button does in system contexts, missing ones do nothing.
+    #[serde(default = "default_system_buttons")]
+    pub system_buttons: Vec<SystemButton>,
+    /// Response to normal pressure, reported as CSR_NPRESPONSE.
+    #[serde(default, deserialize_with = "deserialize_pressure")]
+    pub pressure: PressureConfig,
+}
+
+/// Logical buttons are bits of pkButtons, so only 0 to 31 can be mapped to.
+fn deserialize_button_map<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let map = Vec::<u8>::deserialize(deserializer)?;
+    if let Some(button) = map.iter().find(|x| **x >= 32) {
+        return Err(serde::de::Error::custom(format!(
+            "logical button {} is out of range (0-31)",
+            button
+        )));
+    }
+    Ok(map)
+}
+
+/// Curves have to rise from `min` to `max`, through points on the unit square.
+fn deserialize_pressure<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PressureConfig, D::Error> {
+    let pressure = PressureConfig::deserialize(deserializer)?;
+    let invalid = |msg: String| Err(serde::de::Error::custom(msg));
+    if pressure.min >= pressure.max {
+        return invalid(format!(
+            "pressure min {} isn't below max {}",
+            pressure.min, pressure.max
+        ));
+    }
+    if pressure.gamma <= 0.0 {
+        return invalid(format!("pressure gamma {} isn't positive", pressure.gamma));
+    }
+    let outside = |point: &&[f64; 2]| point.iter().any(|x| !(0.0..=1.0).contains(x));
+    if let Some(point) = pressure.points.iter().find(outside) {
+        return invalid(format!("pressure point {:?} is out of range (0-1)", point));
+    }
+    if let Some(pair) = pressure.points.windows(2).find(|x| x[1][0] <= x[0][0]) {
+        return invalid(format!(
+            "pressure point {:?} doesn't come after {:?}",
+            pair[1], pair[0]
+        ));
+    }
+    let [release, press] = pressure.button_marks;
+    if release > press {
+        return invalid(format!(
+            "pressure button release mark {} is above the press mark {}",
+            release, press
+        ));
+    }
+    Ok(pressure)
+}
+
+/// A pressure response curve. Pressures are fractions of the device's normal pressure axis.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PressureConfig {
+    /// Pressure below which nothing is reported.
+    pub min: f64,
+    /// Pressure from which full pressure is reported.
+    pub max: f64,
+    /// Exponent of the curve between the thresholds, 1 is linear.
+    pub gamma: f64,
+    /// Control points (pressure, response) of a piecewise linear curve between the thresholds,
+    /// from (0, 0) to (1, 1). Used instead of `gamma` when given.
+    pub points: Vec<[f64; 2]>,
+    /// Pressures where the tip button is released and pressed, reported on the axis as CSR_NPBTNMARKS.
+    pub button_marks: [f64; 2],
+}
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: 1.0,
+            gamma: 1.0,
+            points: Vec::new(),
+            button_marks: [0.01, 0.03],
+        }
+    }
+}
+
+/// System button actions (SBN_*), e.g. what a button clicks in a system context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemButton {
+    None,
+    LeftClick,
+    LeftDoubleClick,
+    LeftDrag,
+    RightClick,
+    RightDoubleClick,
+    RightDrag,
+    MiddleClick,
+    MiddleDoubleClick,
+    MiddleDrag,
+}
+impl SystemButton {
+    /// The SBN_* code of the action.
+    pub const fn code(self) -> u8 {
+        match self {
+            SystemButton::None => SBN_NONE,
+            SystemButton::LeftClick => SBN_LCLICK,
+            SystemButton::LeftDoubleClick => SBN_LDBLCLICK,
+            SystemButton::LeftDrag => SBN_LDRAG,
+            SystemButton::RightClick => SBN_RCLICK,
+            SystemButton::RightDoubleClick => SBN_RDBLCLICK,
+            SystemButton::RightDrag => SBN_RDRAG,
+            SystemButton::MiddleClick => SBN_MCLICK,
+            SystemButton::MiddleDoubleClick => SBN_MDBLCLICK,
+            SystemButton::MiddleDrag => SBN_MDRAG,
+        }
+    }
+}
+
+fn default_system_buttons() -> Vec<SystemButton> {
+    vec![
+        SystemButton::LeftClick,
+        SystemButton::RightClick,
+        SystemButton::MiddleClick,
+    ]
+}
+
+pub fn default_cursors() -> Vec<CursorConfig> {
+    vec![
+        CursorConfig {
+            name: "Puck".into(),
+            kind: CursorKind::Puck,
+            buttons: 5,
+            physical_id: 1,
+            button_names: Vec::new(),
+            button_map: Vec::new(),
+            system_buttons: default_system_buttons(),
+            pressure: PressureConfig::default(),
+        },
+        CursorConfig {
+            name: "Pressure Stylus".into(),
+            kind: CursorKind::Pen,
+            buttons: 3,
+            physical_id: 2,
+            button_names: vec![
+                "Tip".into(),
+                "Lower Side Button".into(),
+                "Upper Side Button".into(),
+            ],
+            button_map: Vec::new(),
+            system_buttons: default_system_buttons(),
+            pressure: PressureConfig::default(),
+        },
+        CursorConfig {
+            name: "Eraser".into(),
+            kind: CursorKind::Eraser,
+            buttons: 3,
+            physical_id: 2,
+            button_names: vec![
+                "Eraser".into(),
+                "Lower Side Button".into(),
+                "Upper Side Button".into(),
+            ],
+            button_map: Vec::new(),
+            system_buttons: default_system_buttons(),
+            pressure: PressureConfig::default(),
+        },
+    ]
+}
+
 pub fn find_config() -> color_eyre::Result<Config> {
     let mut file_result = std::fs::File::open("psm.json");
     if let Err(_) = file_result {
@@ -0,0 +1,266 @@
+use std::ffi::c_void;
+
+use crate::{
+    charset::Charset,
+    config::ExtensionsConfig,
+    ffi::{Axis, TU_NONE},
+    info_write::{info_write, info_write_slice, info_write_str},
+    ptr::copy,
+};
+
+// Extension tags
+/// ExpressKeys (added in spec 1.4)
+pub const WTX_EXPKEYS2: u32 = 8;
+/// TouchStrips (added in spec 1.4)
+pub const WTX_TOUCHSTRIP: u32 = 6;
+/// TouchRings (added in spec 1.4)
+pub const WTX_TOUCHRING: u32 = 7;
+
+// WTPKT bits selecting the extensions, returned from EXT_MASK
+pub const PKEXT_EXPKEYS2: u32 = 0x0001_0000;
+pub const PKEXT_TOUCHSTRIP: u32 = 0x0002_0000;
+pub const PKEXT_TOUCHRING: u32 = 0x0004_0000;
+
+// WTI_EXTENSIONS indexes
+pub const EXT_NAME: u32 = 1;
+pub const EXT_TAG: u32 = 2;
+pub const EXT_MASK: u32 = 3;
+pub const EXT_SIZE: u32 = 4;
+pub const EXT_AXES: u32 = 5;
+pub const EXT_DEFAULT: u32 = 6;
+pub const EXT_DEFCONTEXT: u32 = 7;
+pub const EXT_DEFSYSCTX: u32 = 8;
+
+// EXTPROPERTY property IDs
+pub const TABLET_PROPERTY_CONTROLCOUNT: u16 = 0;
+pub const TABLET_PROPERTY_FUNCCOUNT: u16 = 1;
+pub const TABLET_PROPERTY_AVAILABLE: u16 = 2;
+pub const TABLET_PROPERTY_MIN: u16 = 3;
+pub const TABLET_PROPERTY_MAX: u16 = 4;
+pub const TABLET_PROPERTY_OVERRIDE: u16 = 5;
+pub const TABLET_PROPERTY_OVERRIDE_NAME: u16 = 6;
+pub const TABLET_PROPERTY_LOCATION: u16 = 11;
+
+/// Number of extensions PSM supports.
+pub const NUM_EXTENSIONS: u32 = 3;
+
+pub struct WtiExtension {
+    /// Returns a unique null-terminated string describing the extension.
+    pub name: &'static str,
+    /// Returns a unique identifier for the extension.
+    pub tag: u32,
+    /// (WTPKT) Returns a mask that can be bitwise OR'ed with WTPKT-type variables to select the extension.
+    pub mask: u32,
+    /// Returns an array of two UINTs specifying the extension's size within a packet (in bytes).
+    /// The first is for absolute mode; the second is for relative mode.
+    pub size: [u32; 2],
+    /// Returns an array of axis descriptions, as needed for the extension.
+    pub axes: Vec<Axis>,
+    /// Number of controls of this kind on the pad.
+    pub controls: u32,
+    /// Number of functions (modes) of each control.
+    pub functions: u32,
+    /// Location of the controls on the pad, see [ExtensionsConfig::express_keys_location].
+    pub location: u32,
+}
+impl WtiExtension {
+    /// Extensions in their category order, starting at WTI_EXTENSIONS.
+    pub fn psm_extensions(config: &ExtensionsConfig) -> Vec<Self> {
+        let slider_axes = |sliders: &[crate::config::SliderConfig]| {
+            sliders
+                .iter()
+                .map(|x| Axis {
+                    min: 0,
+                    max: x.max as i32,
+                    units: TU_NONE,
+                    resolution: 0,
+                })
+                .collect::<Vec<_>>()
+        };
+        let slider_size = size_of::<SliderData>() as u32;
+        vec![
+            WtiExtension {
+                name: "ExpressKeys",
+                tag: WTX_EXPKEYS2,
+                mask: PKEXT_EXPKEYS2,
+                size: [size_of::<ExpKeysData>() as u32; 2],
+                axes: Vec::new(),
+                controls: config.express_keys,
+                functions: 1,
+                location: config.express_keys_location as u32,
+            },
+            WtiExtension {
+                name: "TouchStrips",
+                tag: WTX_TOUCHSTRIP,
+                mask: PKEXT_TOUCHSTRIP,
+                size: [slider_size; 2],
+                axes: slider_axes(&config.touch_strips),
+                controls: config.touch_strips.len() as u32,
+                functions: config
+                    .touch_strips
+                    .iter()
+                    .map(|x| x.modes)
+                    .max()
+                    .unwrap_or(0),
+                location: 0,
+            },
+            WtiExtension {
+                name: "TouchRings",
+                tag: WTX_TOUCHRING,
+                mask: PKEXT_TOUCHRING,
+                size: [slider_size; 2],
+                axes: slider_axes(&config.touch_rings),
+                controls: config.touch_rings.len() as u32,
+                functions: config
+                    .touch_rings
+                    .iter()
+                    .map(|x| x.modes)
+                    .max()
+                    .unwrap_or(0),
+                location: 0,
+            },
+        ]
+    }
+
+    /// # Safety
+    /// `lp_output` must be null or valid for writing the requested item.
+    pub unsafe fn handle_info(&self, index: u32, charset: Charset, lp_output: *mut c_void) -> u32 {
+        unsafe {
+            match index {
+                EXT_NAME => info_write_str(self.name, charset, lp_output),
+                EXT_TAG => info_write(&self.tag, lp_output),
+                EXT_MASK => info_write(&self.mask, lp_output),
+                EXT_SIZE => info_write(&self.size, lp_output),
+                EXT_AXES => info_write_slice(&self.axes, lp_output),
+                // including EXT_DEFAULT, EXT_DEFCONTEXT and EXT_DEFSYSCTX, there are no defaults
+                _ => 0,
+            }
+        }
+    }
+
+    /// Reads a property of one of the extension's controls.
+    /// Returns [None] if the property isn't supported.
+    pub fn property(&self, control: u8, function: u8, property: u16) -> Option<u32> {
+        if property != TABLET_PROPERTY_CONTROLCOUNT
+            && (control as u32 >= self.controls || function as u32 >= self.functions.max(1))
+        {
+            return None;
+        }
+        match property {
+            TABLET_PROPERTY_CONTROLCOUNT => Some(self.controls),
+            TABLET_PROPERTY_FUNCCOUNT => Some(self.functions),
+            TABLET_PROPERTY_AVAILABLE => Some(1),
+            TABLET_PROPERTY_MIN => Some(0),
+            TABLET_PROPERTY_MAX => Some(
+                self.axes
+                    .get(control as usize)
+                    .map(|x| x.max as u32)
+                    .unwrap_or(1),
+            ),
+            TABLET_PROPERTY_LOCATION => Some(self.location),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+/// The EXTPROPERTY header used by WTExtGet and WTExtSet. `data_size` bytes of data follow it.
+pub struct ExtProperty {
+    /// Structure version, 0 for now.
+    pub version: u8,
+    /// 0-based index for tablet.
+    pub tablet_index: u8,
+    /// 0-based index for control.
+    pub control_index: u8,
+    /// 0-based index for control's sub-function.
+    pub function_index: u8,
+    /// Property ID.
+    pub property_id: u16,
+    /// DWORD-alignment filler.
+    pub reserved: u16,
+    /// Number of bytes in the data buffer.
+    pub data_size: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+/// The EXTENSIONBASE data structure, common to all extension packets.
+pub struct ExtensionBase {
+    /// Specifies the context that generated the event.
+    pub context: usize,
+    /// Specifies various status and error conditions.
+    pub status: u32,
+    /// Specifies the system time at which the event was posted.
+    pub time: u32,
+    /// Contains a serial number assigned to the packet by the context.
+    pub serial: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+/// The EXPKEYSDATA data structure.
+pub struct ExpKeysData {
+    /// Tablet index.
+    pub tablet: u8,
+    /// Zero-based index of the ExpressKey.
+    pub control: u8,
+    /// Location of the ExpressKey: 0 - left, 1 - right.
+    pub location: u8,
+    pub reserved: u8,
+    /// 1 if the key is pressed, 0 if released.
+    pub state: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+/// The SLIDERDATA data structure, used by both touch strips and touch rings.
+pub struct SliderData {
+    /// Tablet index.
+    pub tablet: u8,
+    /// Zero-based index of the strip or ring.
+    pub control: u8,
+    /// Zero-based index of the current mode.
+    pub mode: u8,
+    pub reserved: u8,
+    /// Touch position, 0 when the finger is lifted.
+    pub position: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+/// The PACKETEXT data structure, delivered with WT_PACKETEXT.
+/// Like [Packet](crate::ffi::Packet), only the extensions selected in lcPktData are written.
+pub struct PacketExt {
+    pub base: ExtensionBase,
+    pub exp_keys: ExpKeysData,
+    pub touch_strip: SliderData,
+    pub touch_ring: SliderData,
+}
+impl PacketExt {
+    pub fn write(&self, start_ptr: *mut c_void, mask: u32) -> u32 {
+        let mut ptr = start_ptr;
+        unsafe {
+            let written = copy(&self.base, ptr as *mut _, 1);
+            ptr = ptr.wrapping_add(written);
+        }
+        if mask & PKEXT_EXPKEYS2 > 0 {
+            unsafe {
+                let written = copy(&self.exp_keys, ptr as *mut _, 1);
+                ptr = ptr.wrapping_add(written);
+            }
+        }
+        if mask & PKEXT_TOUCHSTRIP > 0 {
+            unsafe {
+                let written = copy(&self.touch_strip, ptr as *mut _, 1);
+                ptr = ptr.wrapping_add(written);
+            }
+        }
+        if mask & PKEXT_TOUCHRING > 0 {
+            unsafe {
+                let written = copy(&self.touch_ring, ptr as *mut _, 1);
+                ptr = ptr.wrapping_add(written);
+            }
+        }
+        (ptr as usize - start_ptr as usize) as u32
+    }
+}
@@ -1,8 +1,13 @@
 use std::ffi::c_void;
 
 use crate::{
-    info_write::{info_write, info_write_array},
-    ptr::copy,
+    charset::Charset,
+    config::{CursorConfig, CursorKind, TabletPreset},
+    ext::NUM_EXTENSIONS,
+    info_write::{InfoCategory, bytes_of, info_write},
+    manager::MAX_MANAGERS,
+    pressure::{PressureCurve, RESPONSE_SAMPLES},
+    save::SAVE_SIZE,
 };
 
 /// Returns [WtiInterface]
@@ -83,18 +88,16 @@ pub const TPS_MARGIN: u32 = 0x0004;
 pub const TPS_GRAB: u32 = 0x0008;
 // Specifies that the cursor is in its inverted state. (added in spec 1.1)
 pub const TPS_INVERT: u32 = 0x0010;
+/// Status bits a client may report in its packets.
+pub const CLIENT_STATUS_MASK: u32 = TPS_PROXIMITY | TPS_INVERT;
 
-// TODO: make structs more Rust-y and make a custom writer
-// for them, instead of limiting to static sizes.
-// Something like what is done to [Packet],
-// then we can have dynamic names for everything.
+// Info categories keep their strings as Rust strings and encode them per caller
+// through [InfoCategory], so WTInfoA and WTInfoW each get their own layout.
 
-pub const INTERFACE_WINTABID_LEN: usize = 33; // 16 symbols * 2 bytes (UTF-16) + 1 byte (\0 termination)
-#[repr(C)]
 pub struct WtiInterface {
     /// Returns a copy of the null-terminated tablet hardware identification string in the user buffer.
     /// This string should include make, model, and revision information in user-readable format.
-    pub wintabid: [u8; INTERFACE_WINTABID_LEN],
+    pub wintabid: String,
     /// Returns the specification version number.
     /// The high-order byte contains the major version number; the low-order byte contains the minor version number.
     pub spec_version: u16,
@@ -118,53 +121,123 @@ pub struct WtiInterface {
 }
 impl WtiInterface {
     pub fn psm_default() -> Self {
-        let wintabids = "PAIN STUDIO MASK".encode_utf16().collect::<Vec<u16>>();
-        let mut wintabid = [0u8; INTERFACE_WINTABID_LEN];
-        for i in 0..INTERFACE_WINTABID_LEN {
-            if i % 2 == 1 {
-                continue;
-            }
-            let u16i = i / 2;
-            if wintabids.len() <= u16i {
-                break;
-            }
-            wintabid[i] = wintabids[u16i] as u8;
-            if i + 1 == wintabid.len() {
-                break;
-            }
-            wintabid[i + 1] = (wintabids[u16i] >> 8) as u8;
-        }
-        // debug!("{:#?}", wintabid);
-
         WtiInterface {
-            wintabid,
+            wintabid: "PAIN STUDIO MASK".into(),
             spec_version: 0b00000001_00000001,
             impl_version: 0b00000000_00000001,
             num_devices: 1,
             num_cursors: 1,
             num_contexts: 16,
             ctx_options: 0,
-            ctx_save_size: 0,
-            num_extensions: 0,
-            num_managers: 0,
+            ctx_save_size: SAVE_SIZE as u32,
+            num_extensions: NUM_EXTENSIONS,
+            num_managers: MAX_MANAGERS,
         }
     }
+}
+impl InfoCategory for WtiInterface {
+    const ITEMS: u32 = 10;
+
+    fn item(&self, index: u32, charset: Charset) -> Option<Vec<u8>> {
+        Some(match index {
+            1 => charset.encode_str(&self.wintabid),
+            2 => bytes_of(&self.spec_version),
+            3 => bytes_of(&self.impl_version),
+            4 => bytes_of(&self.num_devices),
+            5 => bytes_of(&self.num_cursors),
+            6 => bytes_of(&self.num_contexts),
+            7 => bytes_of(&self.ctx_options),
+            8 => bytes_of(&self.ctx_save_size),
+            9 => bytes_of(&self.num_extensions),
+            10 => bytes_of(&self.num_managers),
+            _ => return None,
+        })
+    }
+}
 
+// WTI_STATUS indexes
+pub const STA_CONTEXTS: u32 = 1;
+pub const STA_SYSCTXS: u32 = 2;
+pub const STA_PKTRATE: u32 = 3;
+pub const STA_PKTDATA: u32 = 4;
+pub const STA_MANAGERS: u32 = 5;
+pub const STA_SYSTEM: u32 = 6;
+pub const STA_BUTTONUSE: u32 = 7;
+pub const STA_SYSBTNUSE: u32 = 8;
+// PSM-specific WTI_STATUS indexes
+pub const STA_PSM_PENCTXS: u32 = 100;
+pub const STA_PSM_RECEIVED: u32 = 101;
+pub const STA_PSM_DELIVERED: u32 = 102;
+pub const STA_PSM_DROPPED_OVERFLOW: u32 = 103;
+pub const STA_PSM_DROPPED_OUT_OF_RANGE: u32 = 104;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct WtiStatus {
+    /// Returns the number of contexts currently open.
+    pub contexts: u32,
+    /// Returns the number of system contexts currently open.
+    pub sys_contexts: u32,
+    /// Returns the maximum packet report rate currently being received by any context, in Hertz.
+    pub pkt_rate: u32,
+    /// (WTPKT) Returns a mask indicating which packet data items are requested by at least one context.
+    pub pkt_data: u32,
+    /// Returns the number of manager handles currently open.
+    pub managers: u32,
+    /// Returns a non-zero value if system pointing is available to the whole screen; zero otherwise.
+    pub system: u32,
+    /// Returns a button mask indicating the logical buttons whose events are requested by at least one context.
+    pub button_use: u32,
+    /// Returns a button mask indicating which logical buttons are assigned a system button function by the current cursor's system button map.
+    pub sys_btn_use: u32,
+}
+impl WtiStatus {
+    /// # Safety
+    /// `lp_output` must be null or valid for writing the requested item.
     pub unsafe fn handle_info(&self, index: u32, lp_output: *mut c_void) -> u32 {
         unsafe {
             match index {
                 0 => info_write(self, lp_output),
-                1 => info_write_array(&self.wintabid, lp_output, INTERFACE_WINTABID_LEN),
-                // 1 => info_write(&self.wintabid, lp_output),
-                2 => info_write(&self.spec_version, lp_output),
-                3 => info_write(&self.impl_version, lp_output),
-                4 => info_write(&self.num_devices, lp_output),
-                5 => info_write(&self.num_cursors, lp_output),
-                6 => info_write(&self.num_contexts, lp_output),
-                7 => info_write(&self.ctx_options, lp_output),
-                8 => info_write(&self.ctx_save_size, lp_output),
-                9 => info_write(&self.num_extensions, lp_output),
-                10 => info_write(&self.num_managers, lp_output),
+                STA_CONTEXTS => info_write(&self.contexts, lp_output),
+                STA_SYSCTXS => info_write(&self.sys_contexts, lp_output),
+                STA_PKTRATE => info_write(&self.pkt_rate, lp_output),
+                STA_PKTDATA => info_write(&self.pkt_data, lp_output),
+                STA_MANAGERS => info_write(&self.managers, lp_output),
+                STA_SYSTEM => info_write(&self.system, lp_output),
+                STA_BUTTONUSE => info_write(&self.button_use, lp_output),
+                STA_SYSBTNUSE => info_write(&self.sys_btn_use, lp_output),
+                _ => 0,
+            }
+        }
+    }
+}
+
+/// PSM's own WTI_STATUS items, only readable by their index so WTInfo(WTI_STATUS, 0)
+/// stays the size apps expect.
+#[derive(Debug, Default)]
+pub struct PsmStatus {
+    /// (PSM) Returns the number of pen (CXO_PEN) contexts currently open.
+    pub pen_contexts: u32,
+    /// (PSM) Returns the number of tablet packets received from the client.
+    pub received: u32,
+    /// (PSM) Returns the number of packets queued into contexts.
+    pub delivered: u32,
+    /// (PSM) Returns the number of packets lost to full context queues.
+    pub dropped_overflow: u32,
+    /// (PSM) Returns the number of packets dropped for being outside of the context's output area.
+    pub dropped_out_of_range: u32,
+}
+impl PsmStatus {
+    /// # Safety
+    /// `lp_output` must be null or valid for writing the requested item.
+    pub unsafe fn handle_info(&self, index: u32, lp_output: *mut c_void) -> u32 {
+        unsafe {
+            match index {
+                STA_PSM_PENCTXS => info_write(&self.pen_contexts, lp_output),
+                STA_PSM_RECEIVED => info_write(&self.received, lp_output),
+                STA_PSM_DELIVERED => info_write(&self.delivered, lp_output),
+                STA_PSM_DROPPED_OVERFLOW => info_write(&self.dropped_overflow, lp_output),
+                STA_PSM_DROPPED_OUT_OF_RANGE => info_write(&self.dropped_out_of_range, lp_output),
                 _ => 0,
             }
         }
@@ -176,11 +249,9 @@ pub const HWC_TOUCH: u32 = 0x0002;
 pub const HWC_HARDPROX: u32 = 0x0004;
 pub const HWC_PHYSID_CURSORS: u32 = 0x0008;
 
-pub const DEVICE_NAME_LEN: usize = 15; // 7 symbols * 2 bytes (UTF-16) + 1 byte (\0 termination)
-#[repr(C)]
 pub struct WtiDevice {
     /// Returns a displayable null-terminated string describing the device, manufacturer, and revision level.
-    pub name: [u8; DEVICE_NAME_LEN],
+    pub name: String,
     /// Returns flags indicating hardware and driver capabilities, as defined below:
     /// [HWC_INTEGRATED]: Indicates that the display and digitizer share the same surface.
     /// [HWC_TOUCH]: Indicates that the cursor must be in physical contact with the device to report position.
@@ -222,30 +293,12 @@ pub struct WtiDevice {
     /// 3-element array describing the tablet's rotation range and resolution capabilities.
     pub rotation: [Axis; 3],
     /// Null-terminated string containing the device’s Plug and Play ID.
-    pub pnp_id: [u8; 8],
+    pub pnp_id: String,
 }
 impl WtiDevice {
     pub fn psm_default() -> Self {
-        let name_string = "PSMDEV1".encode_utf16().collect::<Vec<u16>>();
-        let mut name = [0u8; DEVICE_NAME_LEN];
-        for i in 0..DEVICE_NAME_LEN {
-            if i % 2 == 1 {
-                continue;
-            }
-            let u16i = i / 2;
-            if name_string.len() <= u16i {
-                break;
-            }
-            name[i] = name_string[u16i] as u8;
-            if i + 1 == name.len() {
-                break;
-            }
-            name[i + 1] = (name_string[u16i] >> 8) as u8;
-        }
-        // debug!("{:#?}", wintabid);
-
         WtiDevice {
-            name,
+            name: "PSMDEV1".into(),
             hardware: HWC_HARDPROX | HWC_PHYSID_CURSORS,
             num_cursor_types: 0,
             first_cursor_type: 0,
@@ -324,38 +377,36 @@ impl WtiDevice {
                 Axis::psm_default(),
                 Axis::psm_default(),
             ],
-            pnp_id: [0u8; 8],
+            pnp_id: String::new(),
         }
     }
+}
+impl InfoCategory for WtiDevice {
+    const ITEMS: u32 = 19;
 
-    pub unsafe fn handle_info(&self, index: u32, lp_output: *mut c_void) -> u32 {
-        unsafe {
-            match index {
-                0 => info_write(self, lp_output),
-                // TODO: replace with non-array version??
-                // 1 => info_write_array(&self.name, lp_output, DEVICES_NAME_LEN),
-                1 => info_write(&self.name, lp_output),
-                2 => info_write(&self.hardware, lp_output),
-                3 => info_write(&self.num_cursor_types, lp_output),
-                4 => info_write(&self.first_cursor_type, lp_output),
-                5 => info_write(&self.packet_rate, lp_output),
-                6 => info_write(&self.packet_data, lp_output),
-                7 => info_write(&self.packet_mode, lp_output),
-                8 => info_write(&self.csr_data, lp_output),
-                9 => info_write(&self.x_margin, lp_output),
-                10 => info_write(&self.y_margin, lp_output),
-                11 => info_write(&self.z_margin, lp_output),
-                12 => info_write(&self.device_x, lp_output),
-                13 => info_write(&self.device_y, lp_output),
-                14 => info_write(&self.device_z, lp_output),
-                15 => info_write(&self.normal_pressure, lp_output),
-                16 => info_write(&self.tangential_pressure, lp_output),
-                17 => info_write(&self.orientation, lp_output),
-                18 => info_write(&self.rotation, lp_output),
-                19 => info_write_array(&self.pnp_id, lp_output, 8),
-                _ => 0,
-            }
-        }
+    fn item(&self, index: u32, charset: Charset) -> Option<Vec<u8>> {
+        Some(match index {
+            1 => charset.encode_str(&self.name),
+            2 => bytes_of(&self.hardware),
+            3 => bytes_of(&self.num_cursor_types),
+            4 => bytes_of(&self.first_cursor_type),
+            5 => bytes_of(&self.packet_rate),
+            6 => bytes_of(&self.packet_data),
+            7 => bytes_of(&self.packet_mode),
+            8 => bytes_of(&self.csr_data),
+            9 => bytes_of(&self.x_margin),
+            10 => bytes_of(&self.y_margin),
+            11 => bytes_of(&self.z_margin),
+            12 => bytes_of(&self.device_x),
+            13 => bytes_of(&self.device_y),
+            14 => bytes_of(&self.device_z),
+            15 => bytes_of(&self.normal_pressure),
+            16 => bytes_of(&self.tangential_pressure),
+            17 => bytes_of(&self.orientation),
+            18 => bytes_of(&self.rotation),
+            19 => charset.encode_str(&self.pnp_id),
+            _ => return None,
+        })
     }
 }
 
@@ -363,11 +414,20 @@ pub const CRC_MULTIMODE: u32 = 0x0001;
 pub const CRC_AGGREGATE: u32 = 0x0002;
 pub const CRC_INVERT: u32 = 0x0004;
 
-pub const CURSOR_NAME_LEN: usize = 15; // 7 symbols * 2 bytes (UTF-16) + 1 byte (\0 termination)
-#[repr(C)]
+pub const SBN_NONE: u8 = 0x00;
+pub const SBN_LCLICK: u8 = 0x01;
+pub const SBN_LDBLCLICK: u8 = 0x02;
+pub const SBN_LDRAG: u8 = 0x03;
+pub const SBN_RCLICK: u8 = 0x04;
+pub const SBN_RDBLCLICK: u8 = 0x05;
+pub const SBN_RDRAG: u8 = 0x06;
+pub const SBN_MCLICK: u8 = 0x07;
+pub const SBN_MDBLCLICK: u8 = 0x08;
+pub const SBN_MDRAG: u8 = 0x09;
+
 pub struct WtiCursor {
     /// Returns a displayable null-terminated string containing the name of the cursor.
-    pub name: [u8; CURSOR_NAME_LEN],
+    pub name: String,
     /// Returns whether the cursor is currently connected. Acts like a bool.
     pub active: u32,
     /// (WTPKT) Returns a bit mask indicating the packet data items supported when this cursor is connected.
@@ -379,8 +439,7 @@ pub struct WtiCursor {
     /// Returns a list of null-terminated strings containing the names of the cursor's buttons.
     /// The number of names in the list is the same as the number of buttons on the cursor.
     /// The names are separated by a single zero character; the list is terminated by two zero characters.
-    /// Replaced by a single u8 to zero it out.
-    pub button_names: u8,
+    pub button_names: Vec<String>,
     /// Returns a 32 byte array of logical button numbers, one for each physical button.
     pub button_map: [u8; 32],
     /// Returns a 32 byte array of button action codes, one for each logical button.
@@ -391,14 +450,14 @@ pub struct WtiCursor {
     /// The first UINT contains the release mark; the second contains the press mark.
     pub npbtnmarks: [u32; 2],
     /// Returns an array of UINTs describing the pressure response curve for normal pressure.
-    pub npresponse: [u32; 256],
+    pub npresponse: [u32; RESPONSE_SAMPLES],
     /// Returns the physical button number of the button that is controlled by tangential pressure.
     pub tangential_button: u8,
     /// Returns an array of two UINTs, specifying the button marks for the tangential pressure button.
     /// The first UINT contains the release mark; the second contains the press mark.
     pub tpbtnmarks: [u32; 2],
     /// Returns an array of UINTs describing the pressure response curve for tangential pressure.
-    pub tpresponse: [u32; 256],
+    pub tpresponse: [u32; RESPONSE_SAMPLES],
     /// Returns a manufacturer-specific physical identifier for the cursor.
     /// This value will distinguish the physical cursor from others on the same device.
     /// This physical identifier allows applications to bind functions to specific physical cursors,
@@ -415,104 +474,165 @@ pub struct WtiCursor {
     /// [CRC_AGGREGATE]: Indicates this cursor type describes several physical cursors that cannot be distinguished by software.
     /// [CRC_INVERT]: Indicates this cursor type describes the physical cursor in its inverted orientation; the previous consecutive cursor type category describes the normal orientation.
     pub capabilities: u32,
+    /// Applied to normal pressure before delivery, [WtiCursor::npresponse] samples it.
+    pub pressure: PressureCurve,
 }
 impl WtiCursor {
-    pub fn psm_default() -> Self {
-        let name_string = "PSMCUR1".encode_utf16().collect::<Vec<u16>>();
-        let mut name = [0u8; CURSOR_NAME_LEN];
-        for i in 0..CURSOR_NAME_LEN {
-            if i % 2 == 1 {
-                continue;
-            }
-            let u16i = i / 2;
-            if name_string.len() <= u16i {
-                break;
-            }
-            name[i] = name_string[u16i] as u8;
-            if i + 1 == name.len() {
-                break;
-            }
-            name[i + 1] = (name_string[u16i] >> 8) as u8;
-        }
-        // debug!("{:#?}", wintabid);
+    /// A cursor of a device with the given normal and tangential pressure axes.
+    pub fn new(config: &CursorConfig, normal: &Axis, tangential: &Axis) -> Self {
+        let pen_data = PK_CONTEXT
+            | PK_STATUS
+            | PK_TIME
+            | PK_CHANGED
+            | PK_SERIAL_NUMBER
+            | PK_CURSOR
+            | PK_BUTTONS
+            | PK_X
+            | PK_Y
+            | PK_Z
+            | PK_NORMAL_PRESSURE
+            | PK_TANGENT_PRESSURE
+            | PK_ORIENTATION
+            | PK_ROTATION;
+        // a pen and its eraser are two modes of one physical cursor
+        let (packet_data, csr_mode, capabilities) = match config.kind {
+            CursorKind::Puck => (
+                pen_data & !(PK_NORMAL_PRESSURE | PK_TANGENT_PRESSURE | PK_ORIENTATION),
+                0,
+                0,
+            ),
+            CursorKind::Pen => (pen_data, 0, CRC_MULTIMODE),
+            CursorKind::Eraser => (pen_data, 1, CRC_MULTIMODE | CRC_INVERT),
+        };
 
-        let mut response_graph: [u32; 256] = [0u32; 256];
-        for (i, v) in response_graph.iter_mut().enumerate() {
-            *v = (i + 1) as u32;
+        let button_names = (0..config.buttons as usize)
+            .map(|i| match config.button_names.get(i) {
+                Some(name) => name.clone(),
+                None => format!("Button {}", i + 1),
+            })
+            .collect();
+        let mut button_map = [0u8; 32];
+        for (i, logical) in button_map.iter_mut().enumerate() {
+            *logical = config.button_map.get(i).copied().unwrap_or(i as u8);
+        }
+        let mut system_button_map = [SBN_NONE; 32];
+        for (action, system) in system_button_map.iter_mut().zip(&config.system_buttons) {
+            *action = system.code();
         }
 
-        WtiCursor {
-            name,
-            active: 0xFFFFFFFF,
-            packet_data: PK_CONTEXT
-                | PK_STATUS
-                | PK_TIME
-                | PK_CHANGED
-                | PK_SERIAL_NUMBER
-                | PK_CURSOR
-                | PK_BUTTONS
-                | PK_X
-                | PK_Y
-                | PK_Z
-                | PK_NORMAL_PRESSURE
-                | PK_TANGENT_PRESSURE
-                | PK_ORIENTATION
-                | PK_ROTATION,
-            buttons: 32,
-            button_bits: 32,
-            button_names: 0,
-            button_map: [0u8; 32],
-            system_button_map: [0u8; 32],
+        let mut cursor = WtiCursor {
+            name: config.name.clone(),
+            active: 1,
+            packet_data,
+            buttons: config.buttons,
+            button_bits: config.buttons,
+            button_names,
+            button_map,
+            system_button_map,
             physical_button: 0,
-            npbtnmarks: [0, 1],
-            npresponse: response_graph, // [0, 0],
+            npbtnmarks: [0, 0],
+            npresponse: [0; RESPONSE_SAMPLES],
             tangential_button: 1,
-            tpbtnmarks: [0, 1],
-            tpresponse: response_graph, // [0, 0],
-            physical_id: 0,
-            csr_mode: 0,
+            tpbtnmarks: [0, 0],
+            tpresponse: [0; RESPONSE_SAMPLES],
+            physical_id: config.physical_id,
+            csr_mode,
             minpktdata: 0,
             min_buttons: 0,
-            capabilities: 0,
-        }
+            capabilities,
+            pressure: PressureCurve::new(&config.pressure, normal),
+        };
+        cursor.set_axes(normal, tangential);
+        cursor
     }
 
-    pub unsafe fn handle_info(&self, index: u32, lp_output: *mut c_void) -> u32 {
-        unsafe {
-            match index {
-                0 => info_write(self, lp_output),
-                1 => info_write_array(&self.name, lp_output, DEVICE_NAME_LEN),
-                2 => info_write(&self.active, lp_output),
-                3 => info_write(&self.packet_data, lp_output),
-                4 => info_write(&self.buttons, lp_output),
-                5 => info_write(&self.button_bits, lp_output),
-                6 => 0, //info_write(&self.button_names, lp_output),
-                7 => 0, //info_write(&self.button_map, lp_output),
-                8 => 0, //info_write(&self.system_button_map, lp_output),
-                9 => info_write(&self.physical_button, lp_output),
-                10 => 0, //info_write(&self.npbtnmarks, lp_output),
-                11 => 0, //info_write(&self.npresponse, lp_output),
-                12 => info_write(&self.tangential_button, lp_output),
-                13 => 0, //info_write(&self.tpbtnmarks, lp_output),
-                14 => 0, //info_write(&self.tpresponse, lp_output),
-                15 => info_write(&self.physical_id, lp_output),
-                16 => info_write(&self.csr_mode, lp_output),
-                17 => 0, //info_write(&self.minpktdata, lp_output),
-                18 => 0, //info_write(&self.min_buttons, lp_output),
-                19 => info_write(&self.capabilities, lp_output),
-                _ => 0,
-            }
-        }
+    /// Fits the pressure curves to new pressure axes of the device.
+    /// Tangential pressure is reported as is.
+    pub fn set_axes(&mut self, normal: &Axis, tangential: &Axis) {
+        self.pressure = self.pressure.with_axis(normal);
+        self.npbtnmarks = self.pressure.button_marks();
+        self.npresponse = self.pressure.response();
+        let tangential = PressureCurve::linear(tangential);
+        self.tpbtnmarks = tangential.button_marks();
+        self.tpresponse = tangential.response();
+    }
+
+    /// Replaces the normal pressure curve with one following `response`, like CSR_NPRESPONSE.
+    pub fn set_pressure_response(&mut self, response: &[u32; RESPONSE_SAMPLES]) {
+        self.pressure = self.pressure.with_response(response);
+        self.npresponse = self.pressure.response();
+    }
+
+    /// Replaces the normal pressure button marks, like CSR_NPBTNMARKS.
+    /// They stay over the configured ones when the axes change.
+    pub fn set_pressure_button_marks(&mut self, marks: [u32; 2]) {
+        self.pressure = self.pressure.with_button_marks(marks);
+        self.npbtnmarks = self.pressure.button_marks();
+    }
+
+    /// Turns physical button bits from the client into logical ones through [WtiCursor::button_map].
+    pub fn logical_buttons(&self, physical: u32) -> u32 {
+        (0..32)
+            .filter(|i| physical & (1 << i) != 0)
+            .fold(0, |acc, i| acc | 1 << self.button_map[i])
+    }
+
+    /// Logical buttons with a system action (STA_SYSBTNUSE).
+    pub fn system_buttons_used(&self) -> u32 {
+        (0..32)
+            .filter(|i| self.system_button_map[*i] != SBN_NONE)
+            .fold(0, |acc, i| acc | 1 << i)
+    }
+}
+impl InfoCategory for WtiCursor {
+    const ITEMS: u32 = 19;
+
+    fn item(&self, index: u32, charset: Charset) -> Option<Vec<u8>> {
+        Some(match index {
+            1 => charset.encode_str(&self.name),
+            2 => bytes_of(&self.active),
+            3 => bytes_of(&self.packet_data),
+            4 => bytes_of(&self.buttons),
+            5 => bytes_of(&self.button_bits),
+            6 => charset.encode_list(&self.button_names),
+            7 => bytes_of(&self.button_map),
+            8 => bytes_of(&self.system_button_map),
+            9 => bytes_of(&self.physical_button),
+            10 => bytes_of(&self.npbtnmarks),
+            11 => bytes_of(&self.npresponse),
+            12 => bytes_of(&self.tangential_button),
+            13 => bytes_of(&self.tpbtnmarks),
+            14 => bytes_of(&self.tpresponse),
+            15 => bytes_of(&self.physical_id),
+            16 => bytes_of(&self.csr_mode),
+            19 => bytes_of(&self.capabilities),
+            // minimum packet data and buttons aren't reported yet
+            _ => return None,
+        })
     }
 }
 
-pub const LOGICAL_CONTEXT_NAMELEN: usize = 80;
-#[derive(Debug)]
-#[repr(C, align(4))]
+/// Length of `lcName` in characters, including the null terminator.
+pub const LCNAMELEN: usize = 40;
+/// Number of LOGCONTEXT fields after the name, all of them 4 bytes.
+pub const LOGCONTEXT_FIELDS: usize = 33;
+
+/// LOGCONTEXTA and LOGCONTEXTW as apps pass them, `C` being the character type of the name.
+/// Converted from and to [WtiLogicalContext] with [WtiLogicalContext::from_bytes]
+/// and [WtiLogicalContext::to_bytes].
+#[repr(C)]
+pub struct RawLogContext<C> {
+    pub name: [C; LCNAMELEN],
+    pub fields: [u32; LOGCONTEXT_FIELDS],
+}
+pub type LogContextA = RawLogContext<u8>;
+pub type LogContextW = RawLogContext<u16>;
+
+#[derive(Debug, Clone)]
 pub struct WtiLogicalContext {
-    /// Returns a 40 character array (=80 bytes) containing the default name in UTF-16.
+    /// Returns a 40 character array containing the default name.
     /// The name may occupy 0-39 characters; the remainder of the array is padded with zeroes.
-    pub name: [u8; LOGICAL_CONTEXT_NAMELEN],
+    pub name: String,
     /// Returns option flags.
     /// For the default digitizing context, CXO_MARGIN and CXO_MGNINSIDE are allowed.
     /// For the default system context, CXO_SYSTEM is required; CXO_PEN, CXO_MARGIN, and CXO_MGNINSIDE are allowed.
@@ -586,26 +706,8 @@ pub struct WtiLogicalContext {
 }
 impl WtiLogicalContext {
     pub fn psm_default() -> Self {
-        let name_string = "LOGCTX".encode_utf16().collect::<Vec<u16>>();
-        let mut name = [0u8; LOGICAL_CONTEXT_NAMELEN];
-        for i in 0..LOGICAL_CONTEXT_NAMELEN {
-            if i % 2 == 1 {
-                continue;
-            }
-            let u16i = i / 2;
-            if name_string.len() <= u16i {
-                break;
-            }
-            name[i] = name_string[u16i] as u8;
-            if i + 1 == name.len() {
-                break;
-            }
-            name[i + 1] = (name_string[u16i] >> 8) as u8;
-        }
-        // debug!("{:#?}", wintabid);
-
         WtiLogicalContext {
-            name,
+            name: "LOGCTX".into(),
             options: CXO_SYSTEM,
             status: 0,
             locks: 0,
@@ -668,55 +770,156 @@ impl WtiLogicalContext {
         }
     }
 
-    pub unsafe fn handle_info(&self, index: u32, lp_output: *mut c_void) -> u32 {
+    /// Default digitizing or system (CXO_SYSTEM) context of the device at `device`.
+    pub fn from_preset(preset: &TabletPreset, device: u32, system: bool) -> Self {
+        WtiLogicalContext {
+            options: if system { CXO_SYSTEM } else { 0 },
+            device,
+            status: preset.status,
+            packet_rate: preset.packet_rate,
+            packet_mode: preset.packet_mode,
+            move_mask: preset.move_mask,
+            in_org_x: preset.in_org_x,
+            in_org_y: preset.in_org_y,
+            in_org_z: preset.in_org_z,
+            in_ext_x: preset.in_ext_x,
+            in_ext_y: preset.in_ext_y,
+            in_ext_z: preset.in_ext_z,
+            out_org_x: preset.out_org_x,
+            out_org_y: preset.out_org_y,
+            out_org_z: preset.out_org_z,
+            out_ext_x: preset.out_ext_x,
+            out_ext_y: preset.out_ext_y,
+            out_ext_z: preset.out_ext_z,
+            sys_org_x: preset.sys_org_x,
+            sys_org_y: preset.sys_org_y,
+            sys_ext_x: preset.sys_ext_x,
+            sys_ext_y: preset.sys_ext_y,
+            ..Self::psm_default()
+        }
+    }
+
+    /// Size of LOGCONTEXTA or LOGCONTEXTW.
+    pub const fn size(charset: Charset) -> usize {
+        LCNAMELEN * charset.unit_size() + LOGCONTEXT_FIELDS * 4
+    }
+
+    /// Encodes the context as LOGCONTEXTA or LOGCONTEXTW.
+    pub fn to_bytes(&self, charset: Charset) -> Vec<u8> {
+        self.info(0, charset).unwrap_or_default()
+    }
+
+    /// Decodes a LOGCONTEXTA or LOGCONTEXTW, `data` must be [WtiLogicalContext::size] long.
+    pub fn from_bytes(data: &[u8], charset: Charset) -> Self {
+        let (name, fields) = data.split_at(LCNAMELEN * charset.unit_size());
+        let mut fields = fields
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]));
+        let mut next = || fields.next().unwrap_or(0);
+        WtiLogicalContext {
+            name: charset.decode(name),
+            options: next(),
+            status: next(),
+            locks: next(),
+            msg_base: next(),
+            device: next(),
+            packet_rate: next(),
+            packet_data: next(),
+            packet_mode: next(),
+            move_mask: next(),
+            btn_dn_mask: next(),
+            btn_up_mask: next(),
+            in_org_x: next() as i32,
+            in_org_y: next() as i32,
+            in_org_z: next() as i32,
+            in_ext_x: next() as i32,
+            in_ext_y: next() as i32,
+            in_ext_z: next() as i32,
+            out_org_x: next() as i32,
+            out_org_y: next() as i32,
+            out_org_z: next() as i32,
+            out_ext_x: next() as i32,
+            out_ext_y: next() as i32,
+            out_ext_z: next() as i32,
+            out_sens_x: next() as i32,
+            out_sens_y: next() as i32,
+            out_sens_z: next() as i32,
+            sys_mode: next() as i32,
+            sys_org_x: next() as i32,
+            sys_org_y: next() as i32,
+            sys_ext_x: next() as i32,
+            sys_ext_y: next() as i32,
+            sys_sens_x: next() as i32,
+            sys_sens_y: next() as i32,
+        }
+    }
+
+    /// # Safety
+    /// `ptr` must point to a valid LOGCONTEXTA or LOGCONTEXTW, depending on `charset`.
+    pub unsafe fn read_ptr(ptr: *const c_void, charset: Charset) -> Self {
+        let data = unsafe { std::slice::from_raw_parts(ptr as *const u8, Self::size(charset)) };
+        Self::from_bytes(data, charset)
+    }
+
+    /// # Safety
+    /// `ptr` must be valid for writing a LOGCONTEXTA or LOGCONTEXTW, depending on `charset`.
+    pub unsafe fn write_ptr(&self, ptr: *mut c_void, charset: Charset) {
+        let data = self.to_bytes(charset);
         unsafe {
-            match index {
-                0 => info_write(self, lp_output),
-                1 => info_write_array(&self.name, lp_output, LOGICAL_CONTEXT_NAMELEN),
-                2 => info_write(&self.options, lp_output),
-                3 => info_write(&self.status, lp_output),
-                4 => info_write(&self.locks, lp_output),
-                5 => info_write(&self.msg_base, lp_output),
-                6 => info_write(&self.device, lp_output),
-                7 => info_write(&self.packet_rate, lp_output),
-                8 => info_write(&self.packet_data, lp_output),
-                9 => info_write(&self.packet_mode, lp_output),
-                10 => info_write(&self.move_mask, lp_output),
-                11 => info_write(&self.btn_dn_mask, lp_output),
-                12 => info_write(&self.btn_up_mask, lp_output),
-                13 => info_write(&self.in_org_x, lp_output),
-                14 => info_write(&self.in_org_y, lp_output),
-                15 => info_write(&self.in_org_z, lp_output),
-                16 => info_write(&self.in_ext_x, lp_output),
-                17 => info_write(&self.in_ext_y, lp_output),
-                18 => info_write(&self.in_ext_z, lp_output),
-                19 => info_write(&self.out_org_x, lp_output),
-                20 => info_write(&self.out_org_y, lp_output),
-                21 => info_write(&self.out_org_z, lp_output),
-                22 => info_write(&self.out_ext_x, lp_output),
-                23 => info_write(&self.out_ext_y, lp_output),
-                24 => info_write(&self.out_ext_z, lp_output),
-                25 => info_write(&self.out_sens_x, lp_output),
-                26 => info_write(&self.out_sens_y, lp_output),
-                27 => info_write(&self.out_sens_z, lp_output),
-                28 => info_write(&self.sys_mode, lp_output),
-                29 => info_write(&self.sys_org_x, lp_output),
-                30 => info_write(&self.sys_org_y, lp_output),
-                31 => info_write(&self.sys_ext_x, lp_output),
-                32 => info_write(&self.sys_ext_y, lp_output),
-                33 => info_write(&self.sys_sens_x, lp_output),
-                34 => info_write(&self.sys_sens_y, lp_output),
-                _ => 0,
-            }
+            std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
         }
     }
 }
+impl InfoCategory for WtiLogicalContext {
+    const ITEMS: u32 = 34;
+
+    fn item(&self, index: u32, charset: Charset) -> Option<Vec<u8>> {
+        Some(match index {
+            1 => charset.encode_fixed(&self.name, LCNAMELEN),
+            2 => bytes_of(&self.options),
+            3 => bytes_of(&self.status),
+            4 => bytes_of(&self.locks),
+            5 => bytes_of(&self.msg_base),
+            6 => bytes_of(&self.device),
+            7 => bytes_of(&self.packet_rate),
+            8 => bytes_of(&self.packet_data),
+            9 => bytes_of(&self.packet_mode),
+            10 => bytes_of(&self.move_mask),
+            11 => bytes_of(&self.btn_dn_mask),
+            12 => bytes_of(&self.btn_up_mask),
+            13 => bytes_of(&self.in_org_x),
+            14 => bytes_of(&self.in_org_y),
+            15 => bytes_of(&self.in_org_z),
+            16 => bytes_of(&self.in_ext_x),
+            17 => bytes_of(&self.in_ext_y),
+            18 => bytes_of(&self.in_ext_z),
+            19 => bytes_of(&self.out_org_x),
+            20 => bytes_of(&self.out_org_y),
+            21 => bytes_of(&self.out_org_z),
+            22 => bytes_of(&self.out_ext_x),
+            23 => bytes_of(&self.out_ext_y),
+            24 => bytes_of(&self.out_ext_z),
+            25 => bytes_of(&self.out_sens_x),
+            26 => bytes_of(&self.out_sens_y),
+            27 => bytes_of(&self.out_sens_z),
+            28 => bytes_of(&self.sys_mode),
+            29 => bytes_of(&self.sys_org_x),
+            30 => bytes_of(&self.sys_org_y),
+            31 => bytes_of(&self.sys_ext_x),
+            32 => bytes_of(&self.sys_ext_y),
+            33 => bytes_of(&self.sys_sens_x),
+            34 => bytes_of(&self.sys_sens_y),
+            _ => return None,
+        })
+    }
+}
 
 pub const TU_NONE: u32 = 0;
 pub const TU_INCHES: u32 = 1;
 pub const TU_CENTIMETERS: u32 = 2;
 pub const TU_CIRCLE: u32 = 3;
 
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 /// The AXIS data structure defines the range and resolution for many of the packet data items.
 pub struct Axis {
@@ -741,7 +944,7 @@ impl Axis {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Default, Clone)]
 #[repr(C)]
 /// The PACKET data structure is a flexible structure that contains tablet event information. Each of its fields is optional.
 /// The structure consists of a concatenation of the data items selected in the lcPktData field of the context that generated the packet.
@@ -790,97 +993,6 @@ pub struct Packet {
     /// Contains updated cursor rotation information. (see [Rotation])
     pub rotation: Rotation,
 }
-impl Packet {
-    // TODO: make mask a bitfield (PK_CONTEXT and stuff too)
-    pub fn write(&self, start_ptr: *mut c_void, mask: u32) -> u32 {
-        let mut ptr = start_ptr;
-        if mask & PK_CONTEXT > 0 {
-            unsafe {
-                let written = copy(&self.context, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_STATUS > 0 {
-            unsafe {
-                let written = copy(&self.status, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_TIME > 0 {
-            unsafe {
-                let written = copy(&self.time, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_CHANGED > 0 {
-            unsafe {
-                let written = copy(&self.changed, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_SERIAL_NUMBER > 0 {
-            unsafe {
-                let written = copy(&self.serial, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_CURSOR > 0 {
-            unsafe {
-                let written = copy(&self.cursor, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_BUTTONS > 0 {
-            unsafe {
-                let written = copy(&self.buttons, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_X > 0 {
-            unsafe {
-                let written = copy(&self.x, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_Y > 0 {
-            unsafe {
-                let written = copy(&self.y, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_Z > 0 {
-            unsafe {
-                let written = copy(&self.z, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_NORMAL_PRESSURE > 0 {
-            unsafe {
-                let written = copy(&self.normal_pressure, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_TANGENT_PRESSURE > 0 {
-            unsafe {
-                let written = copy(&self.tangential_pressure, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_ORIENTATION > 0 {
-            unsafe {
-                let written = copy(&self.orientation, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        if mask & PK_ROTATION > 0 {
-            unsafe {
-                let written = copy(&self.rotation, ptr as *mut _, 1);
-                ptr = ptr.wrapping_add(written);
-            }
-        }
-        (ptr as usize - start_ptr as usize) as u32
-    }
-}
 
 #[derive(Debug, Default, Clone)]
 #[repr(C)]
@@ -907,6 +1019,7 @@ pub struct Rotation {
     pub yaw: i32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WindowMessage {
     Packet,
     CtxOpen,
@@ -932,4 +1045,82 @@ impl WindowMessage {
             WindowMessage::PacketExt => msg_base + 8,
         }
     }
+
+    /// Whether a context with these options should post this message to its owner.
+    /// [CXO_MESSAGES] gates WT_PACKET and [CXO_CSRMESSAGES] gates WT_CSRCHANGE,
+    /// everything else is always posted.
+    pub fn is_posted(self, options: u32) -> bool {
+        match self {
+            WindowMessage::Packet => options & CXO_MESSAGES != 0,
+            WindowMessage::CsrChange => options & CXO_CSRMESSAGES != 0,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_category_is_the_spec_items() {
+        let status = WtiStatus::default();
+        let psm = PsmStatus::default();
+        let size = unsafe { status.handle_info(0, std::ptr::null_mut()) };
+        assert_eq!(size, 32);
+        let mut buf = [0xaau8; 40];
+        assert_eq!(
+            unsafe { status.handle_info(0, buf.as_mut_ptr() as *mut _) },
+            32
+        );
+        assert_eq!(buf[32..], [0xaa; 8]);
+        assert_eq!(
+            unsafe { status.handle_info(STA_PSM_RECEIVED, buf.as_mut_ptr() as *mut _) },
+            0
+        );
+        assert_eq!(unsafe { psm.handle_info(0, std::ptr::null_mut()) }, 0);
+        assert_eq!(
+            unsafe { psm.handle_info(STA_PSM_RECEIVED, std::ptr::null_mut()) },
+            4
+        );
+    }
+
+    #[test]
+    fn logcontext_layouts() {
+        assert_eq!(size_of::<LogContextA>(), 172);
+        assert_eq!(size_of::<LogContextW>(), 212);
+        let mut lc = WtiLogicalContext::psm_default();
+        lc.name = "Paint".into();
+        lc.in_org_x = -5;
+        for (charset, size) in [
+            (Charset::Ansi, size_of::<LogContextA>()),
+            (Charset::Wide, size_of::<LogContextW>()),
+        ] {
+            assert_eq!(WtiLogicalContext::size(charset), size);
+            let data = lc.to_bytes(charset);
+            assert_eq!(data.len(), size);
+            // the first field comes right after the name
+            let options = LCNAMELEN * charset.unit_size();
+            assert_eq!(data[options..options + 4], lc.options.to_le_bytes());
+            let decoded = WtiLogicalContext::from_bytes(&data, charset);
+            assert_eq!(decoded.name, "Paint");
+            assert_eq!(decoded.in_org_x, -5);
+            assert_eq!(decoded.sys_sens_y, lc.sys_sens_y);
+        }
+        assert_eq!(lc.to_bytes(Charset::Ansi)[..6], *b"Paint\0");
+        assert_eq!(lc.to_bytes(Charset::Wide)[..4], *b"P\0a\0");
+    }
+
+    #[test]
+    fn whole_category_follows_charset() {
+        let interface = WtiInterface::psm_default();
+        let ansi = interface.info(0, Charset::Ansi).unwrap();
+        let wide = interface.info(0, Charset::Wide).unwrap();
+        let id_len = interface.wintabid.len() + 1;
+        assert_eq!(wide.len() - ansi.len(), id_len);
+        assert_eq!(ansi[..id_len], *b"PAIN STUDIO MASK\0");
+        // WTInfo(cat, 0, NULL) reports the same size that's written
+        let written = unsafe { interface.handle_info(0, Charset::Ansi, std::ptr::null_mut()) };
+        assert_eq!(written as usize, ansi.len());
+    }
 }
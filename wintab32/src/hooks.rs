@@ -0,0 +1,303 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use color_eyre::eyre::{ContextCompat, bail};
+use log::{debug, error, info};
+use windows::{
+    Win32::{
+        Foundation::{LPARAM, LRESULT, WPARAM},
+        System::LibraryLoader::{GetProcAddress, LoadLibraryW},
+    },
+    core::{HSTRING, PCSTR},
+};
+
+use crate::{
+    get_state_or_init,
+    layout::PacketLayout,
+    system::{self, SendInputSink},
+};
+
+// Hook types
+/// Supplies packets in place of the tablet.
+pub const WTH_PLAYBACK: i32 = 1;
+/// Receives every packet delivered to a context.
+pub const WTH_RECORD: i32 = 2;
+
+// Hook codes
+pub const WTHC_GETLPLPFN: i32 = -3;
+pub const WTHC_LPLPFNNEXT: i32 = -2;
+pub const WTHC_LPFNNEXT: i32 = -1;
+/// Record hook: lParam points to the delivered packet, in the format of its context.
+pub const WTHC_ACTION: i32 = 0;
+/// Playback hook: copy the next packet to lParam and return the delay before it, in milliseconds.
+/// The packet is in the format of the first device's default digitizing context, see [playback_layout].
+/// PSM treats a negative delay as "no packet yet".
+pub const WTHC_GETNEXT: i32 = 1;
+/// Playback hook: advance to the next packet.
+pub const WTHC_SKIP: i32 = 2;
+
+pub type WTHOOKPROC =
+    unsafe extern "system" fn(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT;
+
+/// How long the playback thread waits when the hook has no packet ready.
+const PLAYBACK_IDLE: Duration = Duration::from_millis(10);
+
+static HOOKS: LazyLock<Mutex<HookChain>> = LazyLock::new(|| Mutex::new(HookChain::default()));
+
+#[derive(Clone, Copy)]
+pub struct PacketHook {
+    pub handle: usize,
+    pub manager: usize,
+    pub kind: i32,
+    pub proc_: WTHOOKPROC,
+}
+
+/// Installed hooks, newest first, like Windows hook chains.
+///
+/// Hook procedures are never called with this lock (or the state lock) held,
+/// since they are expected to call [WTMgrPacketHookNext](crate::WTMgrPacketHookNext).
+#[derive(Default)]
+pub struct HookChain {
+    pub hooks: Vec<PacketHook>,
+    pub counter: usize,
+    /// Packets delivered since the last [flush_records], serialized in their context's layout.
+    pub pending_records: Vec<Vec<u8>>,
+    pub playback_running: bool,
+}
+
+/// Loads `proc_name` from `module` and installs it on top of the chain, returning the hook handle.
+pub fn install(
+    manager: usize,
+    kind: i32,
+    module: &str,
+    proc_name: PCSTR,
+) -> color_eyre::Result<usize> {
+    if kind != WTH_PLAYBACK && kind != WTH_RECORD {
+        bail!("unknown hook type {}", kind);
+    }
+    let proc_ = unsafe {
+        let module = LoadLibraryW(&HSTRING::from(module))?;
+        GetProcAddress(module, proc_name).wrap_err("hook procedure wasn't found in the module")?
+    };
+    let proc_: WTHOOKPROC = unsafe { std::mem::transmute(proc_) };
+    install_proc(manager, kind, proc_)
+}
+
+/// Installs `proc_` on top of the chain, returning the hook handle.
+pub fn install_proc(manager: usize, kind: i32, proc_: WTHOOKPROC) -> color_eyre::Result<usize> {
+    if kind != WTH_PLAYBACK && kind != WTH_RECORD {
+        bail!("unknown hook type {}", kind);
+    }
+    let mut hooks = HOOKS.lock().unwrap();
+    hooks.counter += 1;
+    let handle = hooks.counter;
+    hooks.hooks.insert(
+        0,
+        PacketHook {
+            handle,
+            manager,
+            kind,
+            proc_,
+        },
+    );
+    info!("Installed packet hook {} (type {})", handle, kind);
+    if kind == WTH_PLAYBACK && !hooks.playback_running {
+        hooks.playback_running = true;
+        std::thread::spawn(playback_thread);
+    }
+    Ok(handle)
+}
+
+pub fn uninstall(handle: usize) -> bool {
+    let mut hooks = HOOKS.lock().unwrap();
+    let len = hooks.hooks.len();
+    hooks.hooks.retain(|x| x.handle != handle);
+    hooks.hooks.len() != len
+}
+
+/// Removes the hook with `proc_` installed through the manager, for Wintab 1.0 hooks that have no handle.
+pub fn uninstall_proc(manager: usize, kind: i32, proc_: WTHOOKPROC) -> bool {
+    let mut hooks = HOOKS.lock().unwrap();
+    let pos = hooks
+        .hooks
+        .iter()
+        .position(|x| x.manager == manager && x.kind == kind && x.proc_ as usize == proc_ as usize);
+    pos.map(|x| hooks.hooks.remove(x)).is_some()
+}
+
+/// Removes every hook installed through the manager.
+pub fn uninstall_manager(manager: usize) {
+    let mut hooks = HOOKS.lock().unwrap();
+    hooks.hooks.retain(|x| x.manager != manager);
+}
+
+/// First hook of the given type.
+pub fn first(kind: i32) -> Option<PacketHook> {
+    let hooks = HOOKS.lock().unwrap();
+    hooks.hooks.iter().find(|x| x.kind == kind).copied()
+}
+
+/// Hook of the same type installed before `handle`.
+pub fn next(handle: usize) -> Option<PacketHook> {
+    let hooks = HOOKS.lock().unwrap();
+    let pos = hooks.hooks.iter().position(|x| x.handle == handle)?;
+    let kind = hooks.hooks[pos].kind;
+    hooks.hooks[pos + 1..]
+        .iter()
+        .find(|x| x.kind == kind)
+        .copied()
+}
+
+pub fn call(hook: PacketHook, code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe { (hook.proc_)(code, wparam, lparam) }
+}
+
+pub fn is_playing_back() -> bool {
+    first(WTH_PLAYBACK).is_some()
+}
+
+/// Queues a delivered packet for the record hooks, see [flush_records].
+/// `packet` is serialized lazily, so contexts don't pay for it when nothing records.
+pub fn feed_record(packet: impl FnOnce() -> Vec<u8>) {
+    let mut hooks = HOOKS.lock().unwrap();
+    if hooks.hooks.iter().any(|x| x.kind == WTH_RECORD) {
+        hooks.pending_records.push(packet());
+    }
+}
+
+/// Passes queued packets to the record hooks.
+/// Must be called without holding the state lock.
+pub fn flush_records() {
+    let records = std::mem::take(&mut HOOKS.lock().unwrap().pending_records);
+    for mut packet in records {
+        let Some(hook) = first(WTH_RECORD) else {
+            return;
+        };
+        call(
+            hook,
+            WTHC_ACTION,
+            WPARAM(0),
+            LPARAM(packet.as_mut_ptr() as isize),
+        );
+    }
+}
+
+/// Layout of played back packets: everything the first device's default digitizing context reports, in absolute mode.
+fn playback_layout() -> color_eyre::Result<PacketLayout> {
+    let state = get_state_or_init()?;
+    let lc = state
+        .as_ref()
+        .unwrap()
+        .default_contexts
+        .get(&(0, false))
+        .wrap_err("no default context to play back packets with")?;
+    Ok(PacketLayout::native(lc.packet_data, 0))
+}
+
+/// Pulls packets from the playback hook and delivers them like client packets,
+/// until the last playback hook is removed.
+pub fn playback_thread() {
+    info!("Packet playback started");
+    loop {
+        let hook = {
+            let mut hooks = HOOKS.lock().unwrap();
+            match hooks.hooks.iter().find(|x| x.kind == WTH_PLAYBACK) {
+                Some(hook) => *hook,
+                None => {
+                    hooks.playback_running = false;
+                    break;
+                }
+            }
+        };
+        let layout = match playback_layout() {
+            Ok(v) => v,
+            Err(err) => {
+                error!("{:?}", err);
+                HOOKS.lock().unwrap().playback_running = false;
+                break;
+            }
+        };
+        let mut buf = vec![0u8; layout.size];
+        let delay = call(
+            hook,
+            WTHC_GETNEXT,
+            WPARAM(0),
+            LPARAM(buf.as_mut_ptr() as isize),
+        );
+        if delay.0 < 0 {
+            std::thread::sleep(PLAYBACK_IDLE);
+            continue;
+        }
+        std::thread::sleep(Duration::from_millis(delay.0 as u64));
+        let packet = layout.read(&buf);
+        debug!("playback packet: {:?}", packet);
+        let events = {
+            let mut state = match get_state_or_init() {
+                Ok(v) => v,
+                Err(err) => {
+                    error!("{:?}", err);
+                    HOOKS.lock().unwrap().playback_running = false;
+                    break;
+                }
+            };
+            let state = state.as_mut().unwrap();
+            // recorded packets don't say which tablet they came from
+            state.deliver_packet(0, packet);
+            std::mem::take(&mut state.cursor_events)
+        };
+        system::replay(events, &mut SendInputSink);
+        flush_records();
+        call(hook, WTHC_SKIP, WPARAM(0), LPARAM(0));
+    }
+    info!("Packet playback stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Foundation::HWND;
+
+    use super::*;
+    use crate::{
+        Context, ThreadHWND,
+        ffi::{PK_CONTEXT, PK_X, PK_Y, Packet},
+    };
+
+    /// Handle of the context in [record_hooks_get_the_context_layout], unlike any other test's.
+    const HANDLE: usize = 0xabcd;
+    /// HCTX, pkX and pkY.
+    const SIZE: usize = size_of::<usize>() + 8;
+
+    static RECORDED: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+    unsafe extern "system" fn record(code: i32, _wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        let bytes = unsafe { std::slice::from_raw_parts(lparam.0 as *const u8, SIZE) };
+        if code == WTHC_ACTION && bytes[..size_of::<usize>()] == HANDLE.to_le_bytes() {
+            RECORDED.lock().unwrap().push(bytes.to_vec());
+        }
+        LRESULT(0)
+    }
+
+    #[test]
+    fn record_hooks_get_the_context_layout() {
+        let hook = install_proc(0, WTH_RECORD, record).unwrap();
+        let mut ctx = Context::new(HANDLE, true);
+        ctx.window = ThreadHWND(HWND(0x10 as _));
+        ctx.logical_context.packet_data = PK_CONTEXT | PK_X | PK_Y;
+        ctx.logical_context.packet_mode = 0;
+        ctx.send_packet(Packet {
+            x: 100,
+            y: 200,
+            ..Default::default()
+        })
+        .unwrap();
+        flush_records();
+        uninstall(hook);
+
+        let mut expected = HANDLE.to_le_bytes().to_vec();
+        expected.extend(100u32.to_le_bytes());
+        expected.extend(200u32.to_le_bytes());
+        assert_eq!(*RECORDED.lock().unwrap(), [expected]);
+    }
+}
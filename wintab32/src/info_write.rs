@@ -2,6 +2,8 @@ use std::ffi::c_void;
 
 use log::debug;
 
+use crate::charset::Charset;
+
 pub unsafe fn info_write<T>(data: *const T, lp_output: *mut c_void) -> u32 {
     let size = size_of::<T>() as u32;
     debug!("info_write({:#?}, {:#?}) -> {}", data, lp_output, size);
@@ -14,17 +16,65 @@ pub unsafe fn info_write<T>(data: *const T, lp_output: *mut c_void) -> u32 {
     size
 }
 
-pub unsafe fn info_write_array<T>(data: *const T, lp_output: *mut c_void, len: usize) -> u32 {
-    let size = size_of::<T>() as u32;
+/// # Safety
+/// `lp_output` must be null or valid for writing the whole slice.
+pub unsafe fn info_write_slice<T>(data: &[T], lp_output: *mut c_void) -> u32 {
+    let size = size_of_val(data) as u32;
     debug!(
-        "info_write_array({:#?}, {:#?}, {}) -> {}",
-        data, lp_output, len, size
+        "info_write_slice({:#?}, {:#?}) -> {}",
+        data.as_ptr(),
+        lp_output,
+        size
     );
     if lp_output.is_null() {
         return size;
     }
     unsafe {
-        std::ptr::copy(data, lp_output as *mut _, len);
+        std::ptr::copy(data.as_ptr(), lp_output as *mut _, data.len());
     }
     size
 }
+
+/// Writes a null-terminated string in the caller's charset.
+///
+/// # Safety
+/// `lp_output` must be null or valid for writing the encoded string.
+pub unsafe fn info_write_str(data: &str, charset: Charset, lp_output: *mut c_void) -> u32 {
+    unsafe { info_write_slice(&charset.encode_str(data), lp_output) }
+}
+
+/// The bytes of a plain value, as [info_write] would copy them.
+pub fn bytes_of<T: Copy>(data: &T) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(data as *const T as *const u8, size_of::<T>()) }.to_vec()
+}
+
+/// A WTInfo category whose items are encoded on request, so strings can follow the caller's charset.
+pub trait InfoCategory {
+    /// Index of the last item.
+    const ITEMS: u32;
+
+    /// Encodes the item at `index`, from 1 to [InfoCategory::ITEMS].
+    fn item(&self, index: u32, charset: Charset) -> Option<Vec<u8>>;
+
+    /// Encodes the item at `index`, index 0 being all items back to back.
+    fn info(&self, index: u32, charset: Charset) -> Option<Vec<u8>> {
+        if index != 0 {
+            return self.item(index, charset);
+        }
+        Some(
+            (1..=Self::ITEMS)
+                .filter_map(|x| self.item(x, charset))
+                .flatten()
+                .collect(),
+        )
+    }
+
+    /// # Safety
+    /// `lp_output` must be null or valid for writing the requested item.
+    unsafe fn handle_info(&self, index: u32, charset: Charset, lp_output: *mut c_void) -> u32 {
+        match self.info(index, charset) {
+            Some(data) => unsafe { info_write_slice(&data, lp_output) },
+            None => 0,
+        }
+    }
+}
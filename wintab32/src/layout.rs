@@ -0,0 +1,414 @@
+use std::ffi::c_void;
+
+use crate::ffi::*;
+
+/// One packet data item in a [PacketLayout].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// The PK_* bit of the item.
+    pub item: u32,
+    /// Offset from the start of the packet, in bytes.
+    pub offset: usize,
+    pub size: usize,
+    /// Whether the item is a signed integer (e.g. relative movement).
+    pub signed: bool,
+}
+
+/// PACKET items in the order pktdef.h lays them out, with their absolute mode size.
+/// `None` is the pointer-sized HCTX.
+const ITEMS: [(u32, Option<usize>); 14] = [
+    (PK_CONTEXT, None),
+    (PK_STATUS, Some(4)),
+    (PK_TIME, Some(4)),
+    (PK_CHANGED, Some(4)),
+    (PK_SERIAL_NUMBER, Some(4)),
+    (PK_CURSOR, Some(4)),
+    (PK_BUTTONS, Some(4)),
+    (PK_X, Some(4)),
+    (PK_Y, Some(4)),
+    (PK_Z, Some(4)),
+    (PK_NORMAL_PRESSURE, Some(4)),
+    (PK_TANGENT_PRESSURE, Some(4)),
+    (PK_ORIENTATION, Some(size_of::<Orientation>())),
+    (PK_ROTATION, Some(size_of::<Rotation>())),
+];
+
+/// Items that turn into signed deltas in relative mode (lcPktMode).
+const RELATIVE_ITEMS: u32 = PK_X | PK_Y | PK_Z | PK_NORMAL_PRESSURE | PK_TANGENT_PRESSURE;
+/// Items that are signed in both modes.
+const SIGNED_ITEMS: u32 = PK_ORIENTATION | PK_ROTATION;
+
+/// The packet structure an app declared through lcPktData and lcPktMode,
+/// laid out like the C compiler would for the given pointer width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketLayout {
+    pub fields: Vec<Field>,
+    /// Size of one packet, including the trailing padding, i.e. the stride between packets.
+    pub size: usize,
+}
+impl PacketLayout {
+    pub fn new(packet_data: u32, packet_mode: u32, pointer_size: usize) -> Self {
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        let mut align = 4;
+        for (item, size) in ITEMS {
+            if packet_data & item == 0 {
+                continue;
+            }
+            let size = size.unwrap_or(pointer_size);
+            // only HCTX can be wider than 4 bytes, and it always comes first
+            align = align.max(size.min(pointer_size));
+            fields.push(Field {
+                item,
+                offset,
+                size,
+                signed: item & SIGNED_ITEMS != 0 || item & RELATIVE_ITEMS & packet_mode != 0,
+            });
+            offset += size;
+        }
+        Self {
+            fields,
+            size: offset.next_multiple_of(align),
+        }
+    }
+
+    /// Layout for the pointer width this DLL was built for.
+    pub fn native(packet_data: u32, packet_mode: u32) -> Self {
+        Self::new(packet_data, packet_mode, size_of::<usize>())
+    }
+
+    /// Serializes the packet, `out` must be at least [PacketLayout::size] long.
+    /// Padding bytes are zeroed.
+    pub fn write(&self, packet: &Packet, out: &mut [u8]) {
+        out[..self.size].fill(0);
+        for field in self.fields.iter() {
+            let dst = &mut out[field.offset..field.offset + field.size];
+            match field.item {
+                PK_CONTEXT => {
+                    dst.copy_from_slice(&(packet.context as u64).to_le_bytes()[..field.size])
+                }
+                PK_STATUS => dst.copy_from_slice(&packet.status.to_le_bytes()),
+                PK_TIME => dst.copy_from_slice(&packet.time.to_le_bytes()),
+                PK_CHANGED => dst.copy_from_slice(&packet.changed.to_le_bytes()),
+                PK_SERIAL_NUMBER => dst.copy_from_slice(&packet.serial.to_le_bytes()),
+                PK_CURSOR => dst.copy_from_slice(&packet.cursor.to_le_bytes()),
+                PK_BUTTONS => dst.copy_from_slice(&packet.buttons.to_le_bytes()),
+                PK_X => write_int(dst, packet.x),
+                PK_Y => write_int(dst, packet.y),
+                PK_Z => write_int(dst, packet.z),
+                PK_NORMAL_PRESSURE => write_int(dst, packet.normal_pressure),
+                PK_TANGENT_PRESSURE => write_int(dst, packet.tangential_pressure),
+                PK_ORIENTATION => {
+                    let o = &packet.orientation;
+                    write_ints(dst, [o.azimuth, o.altitude, o.twist]);
+                }
+                PK_ROTATION => {
+                    let r = &packet.rotation;
+                    write_ints(dst, [r.pitch, r.roll, r.yaw]);
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    pub fn to_bytes(&self, packet: &Packet) -> Vec<u8> {
+        let mut out = vec![0u8; self.size];
+        self.write(packet, &mut out);
+        out
+    }
+
+    /// Parses a packet in this layout, `data` must be at least [PacketLayout::size] long.
+    /// Items the layout doesn't have are left at their defaults.
+    pub fn read(&self, data: &[u8]) -> Packet {
+        let mut packet = Packet::default();
+        for field in self.fields.iter() {
+            let src = &data[field.offset..field.offset + field.size];
+            match field.item {
+                // HCTX is a small handle, the upper half of a 64-bit one is always zero
+                PK_CONTEXT => packet.context = read_int(src),
+                PK_STATUS => packet.status = read_int(src),
+                PK_TIME => packet.time = read_int(src),
+                PK_CHANGED => packet.changed = read_int(src),
+                PK_SERIAL_NUMBER => packet.serial = read_int(src),
+                PK_CURSOR => packet.cursor = read_int(src),
+                PK_BUTTONS => packet.buttons = read_int(src),
+                PK_X => packet.x = read_int(src),
+                PK_Y => packet.y = read_int(src),
+                PK_Z => packet.z = read_int(src),
+                PK_NORMAL_PRESSURE => packet.normal_pressure = read_int(src),
+                PK_TANGENT_PRESSURE => packet.tangential_pressure = read_int(src),
+                PK_ORIENTATION => {
+                    let [azimuth, altitude, twist] = read_ints(src);
+                    packet.orientation = Orientation {
+                        azimuth,
+                        altitude,
+                        twist,
+                    };
+                }
+                PK_ROTATION => {
+                    let [pitch, roll, yaw] = read_ints(src);
+                    packet.rotation = Rotation { pitch, roll, yaw };
+                }
+                _ => unreachable!(),
+            }
+        }
+        packet
+    }
+
+    /// Writes `packets` one after another, returning the number of bytes written.
+    ///
+    /// # Safety
+    /// `ptr` must be valid for writing `packets.len()` times [PacketLayout::size] bytes.
+    pub unsafe fn write_ptr<'a>(
+        &self,
+        packets: impl IntoIterator<Item = &'a Packet>,
+        ptr: *mut c_void,
+    ) -> usize {
+        let mut written = 0;
+        for packet in packets {
+            let bytes = self.to_bytes(packet);
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    (ptr as *mut u8).add(written),
+                    bytes.len(),
+                );
+            }
+            written += bytes.len();
+        }
+        written
+    }
+}
+
+/// Writes a DWORD/UINT, or a LONG/int delta in relative mode.
+/// Both have the same bits, since deltas are kept as wrapped u32s.
+fn write_int(dst: &mut [u8], value: u32) {
+    dst.copy_from_slice(&value.to_le_bytes());
+}
+
+/// Turns the items selected in `packet_mode` into deltas from `previous`,
+/// the last packet delivered to the context, in absolute form.
+/// Deltas are kept as wrapped u32s, and the first packet reports no change.
+pub fn to_relative(packet: &mut Packet, previous: Option<&Packet>, packet_mode: u32) {
+    let previous = previous.unwrap_or(packet).clone();
+    let delta = |item: u32, value: &mut u32, last: u32| {
+        if packet_mode & item != 0 {
+            *value = value.wrapping_sub(last);
+        }
+    };
+    delta(PK_TIME, &mut packet.time, previous.time);
+    delta(PK_X, &mut packet.x, previous.x);
+    delta(PK_Y, &mut packet.y, previous.y);
+    delta(PK_Z, &mut packet.z, previous.z);
+    delta(
+        PK_NORMAL_PRESSURE,
+        &mut packet.normal_pressure,
+        previous.normal_pressure,
+    );
+    delta(
+        PK_TANGENT_PRESSURE,
+        &mut packet.tangential_pressure,
+        previous.tangential_pressure,
+    );
+}
+
+fn write_ints(dst: &mut [u8], values: [i32; 3]) {
+    for (chunk, value) in dst.chunks_exact_mut(4).zip(values) {
+        chunk.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Reads the low DWORD of an item.
+fn read_int(src: &[u8]) -> u32 {
+    u32::from_le_bytes(src[..4].try_into().unwrap())
+}
+
+fn read_ints(src: &[u8]) -> [i32; 3] {
+    [0, 1, 2].map(|i| read_int(&src[i * 4..]) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet() -> Packet {
+        Packet {
+            context: 0x0102_0304,
+            status: TPS_INVERT,
+            time: 0x1122_3344,
+            changed: PK_X | PK_Y,
+            serial: 7,
+            cursor: 1,
+            buttons: 0b101,
+            x: 1000,
+            y: (-25i32) as u32,
+            z: 3,
+            normal_pressure: 512,
+            tangential_pressure: 0,
+            orientation: Orientation {
+                azimuth: 1800,
+                altitude: -450,
+                twist: 0,
+            },
+            rotation: Rotation {
+                pitch: 1,
+                roll: -1,
+                yaw: 2,
+            },
+        }
+    }
+
+    fn offsets(layout: &PacketLayout) -> Vec<(u32, usize, usize)> {
+        layout
+            .fields
+            .iter()
+            .map(|x| (x.item, x.offset, x.size))
+            .collect()
+    }
+
+    #[test]
+    fn context_is_pointer_sized() {
+        let x86 = PacketLayout::new(PK_CONTEXT | PK_STATUS, 0, 4);
+        assert_eq!(offsets(&x86), [(PK_CONTEXT, 0, 4), (PK_STATUS, 4, 4)]);
+        assert_eq!(x86.size, 8);
+        assert_eq!(
+            x86.to_bytes(&packet()),
+            [0x04, 0x03, 0x02, 0x01, 0x10, 0, 0, 0]
+        );
+
+        // HCTX is 8 bytes and makes the struct 8-aligned
+        let x64 = PacketLayout::new(PK_CONTEXT | PK_STATUS, 0, 8);
+        assert_eq!(offsets(&x64), [(PK_CONTEXT, 0, 8), (PK_STATUS, 8, 4)]);
+        assert_eq!(x64.size, 16);
+        assert_eq!(
+            x64.to_bytes(&packet()),
+            [
+                0x04, 0x03, 0x02, 0x01, 0, 0, 0, 0, // pkContext
+                0x10, 0, 0, 0, // pkStatus
+                0, 0, 0, 0, // padding
+            ]
+        );
+    }
+
+    #[test]
+    fn subset_is_packed() {
+        let data = PK_BUTTONS | PK_X | PK_Y | PK_NORMAL_PRESSURE;
+        for pointer_size in [4, 8] {
+            let layout = PacketLayout::new(data, 0, pointer_size);
+            assert_eq!(layout.size, 16);
+            assert_eq!(
+                layout.to_bytes(&packet()),
+                [
+                    0b101, 0, 0, 0, // pkButtons
+                    0xe8, 0x03, 0, 0, // pkX
+                    0xe7, 0xff, 0xff, 0xff, // pkY
+                    0, 0x02, 0, 0, // pkNormalPressure
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn full_packet() {
+        let x86 = PacketLayout::new(0x3fff, 0, 4);
+        assert_eq!(x86.size, 4 * 12 + 12 + 12);
+        let x64 = PacketLayout::new(0x3fff, 0, 8);
+        // 76 bytes of fields, padded to 8
+        assert_eq!(x64.size, 80);
+        let x86_bytes = x86.to_bytes(&packet());
+        let x64_bytes = x64.to_bytes(&packet());
+        // everything after pkContext is the same, shifted by 4 bytes
+        assert_eq!(x86_bytes[4..], x64_bytes[8..76]);
+        assert_eq!(x64_bytes[76..], [0, 0, 0, 0]);
+        assert_eq!(
+            x86_bytes[48..],
+            [
+                0x08, 0x07, 0, 0, // azimuth
+                0x3e, 0xfe, 0xff, 0xff, // altitude
+                0, 0, 0, 0, // twist
+                1, 0, 0, 0, // pitch
+                0xff, 0xff, 0xff, 0xff, // roll
+                2, 0, 0, 0, // yaw
+            ]
+        );
+    }
+
+    #[test]
+    fn read_parses_written_packets() {
+        for pointer_size in [4, 8] {
+            let layout = PacketLayout::new(0x3fff, 0, pointer_size);
+            let bytes = layout.to_bytes(&packet());
+            let read = layout.read(&bytes);
+            assert_eq!(layout.to_bytes(&read), bytes);
+            assert_eq!(read.context, 0x0102_0304);
+            assert_eq!(read.orientation.altitude, packet().orientation.altitude);
+        }
+        // missing items stay at their defaults
+        let layout = PacketLayout::native(PK_X | PK_Y, 0);
+        let read = layout.read(&layout.to_bytes(&packet()));
+        assert_eq!((read.x, read.y), (packet().x, packet().y));
+        assert_eq!((read.context, read.status), (0, 0));
+    }
+
+    #[test]
+    fn relative_mode_is_signed() {
+        let layout = PacketLayout::new(PK_BUTTONS | PK_X | PK_Y, PK_X | PK_Y, 8);
+        let signed = layout
+            .fields
+            .iter()
+            .map(|x| (x.item, x.signed))
+            .collect::<Vec<_>>();
+        assert_eq!(signed, [(PK_BUTTONS, false), (PK_X, true), (PK_Y, true)]);
+    }
+
+    #[test]
+    fn relative_items_are_deltas() {
+        let mode = PK_TIME | PK_X | PK_Y | PK_NORMAL_PRESSURE;
+        let mut first = packet();
+        to_relative(&mut first, None, mode);
+        assert_eq!((first.time, first.x, first.y), (0, 0, 0));
+        assert_eq!((first.z, first.normal_pressure), (3, 0));
+
+        let previous = packet();
+        let mut next = Packet {
+            time: previous.time + 5,
+            x: 990,
+            y: 20,
+            z: 4,
+            normal_pressure: 600,
+            ..packet()
+        };
+        to_relative(&mut next, Some(&previous), mode);
+        assert_eq!(next.time, 5);
+        assert_eq!(next.x as i32, -10);
+        assert_eq!(next.y as i32, 45);
+        assert_eq!(next.normal_pressure as i32, 88);
+        // not selected in lcPktMode
+        assert_eq!(next.z, 4);
+
+        let layout = PacketLayout::new(PK_X | PK_Y | PK_Z, mode, 8);
+        assert_eq!(
+            layout.to_bytes(&next),
+            [
+                0xf6, 0xff, 0xff, 0xff, // pkX
+                45, 0, 0, 0, // pkY
+                4, 0, 0, 0, // pkZ
+            ]
+        );
+    }
+
+    #[test]
+    fn packets_are_strided_by_layout_size() {
+        let layout = PacketLayout::new(PK_CONTEXT | PK_SERIAL_NUMBER, 0, 8);
+        let mut first = packet();
+        first.serial = 1;
+        let mut second = packet();
+        second.serial = 2;
+        let mut buf = vec![0xaau8; layout.size * 2];
+        let written = unsafe { layout.write_ptr([&first, &second], buf.as_mut_ptr() as *mut _) };
+        assert_eq!(written, 32);
+        assert_eq!(buf[8..12], [1, 0, 0, 0]);
+        assert_eq!(buf[12..16], [0, 0, 0, 0]);
+        assert_eq!(buf[24..28], [2, 0, 0, 0]);
+    }
+}
@@ -1,29 +1,61 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     ffi::c_void,
     fs::OpenOptions,
     io::{Read, Write},
     net::{TcpListener, TcpStream},
-    sync::{LazyLock, Mutex},
-    time::Instant,
+    sync::{
+        LazyLock, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
 };
 
 use color_eyre::eyre::{ContextCompat, bail};
 use log::{debug, error, info, warn};
 use static_init::{constructor, destructor};
 use windows::Win32::{
-    Foundation::{HWND, LPARAM, WPARAM},
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
     UI::WindowsAndMessaging::*,
 };
+use windows::core::{PCSTR, PCWSTR};
 
-use crate::{config::Config, ffi::*};
-use psm_common::netcode::{COMPATIBLE_VERSION, PSMPacketC2S, PSMPacketS2C};
+use crate::{
+    charset::Charset,
+    clock::{PacketClock, SystemTicks, TimeSource},
+    config::Config,
+    ext::*,
+    ffi::*,
+    info_write::InfoCategory,
+    layout::{PacketLayout, to_relative},
+    manager::*,
+    pressure::RESPONSE_SAMPLES,
+    save::{SAVE_HEADER_SIZE, SavedContext},
+    stats::STATS,
+    system::{CursorEvent, SendInputSink, SystemCursor},
+    throttle::{SystemClock, Throttle},
+    window::{Win32Windows, WindowTracker, post_message},
+};
+use psm_common::netcode::{COMPATIBLE_VERSION, OutOfAreaPolicy, PSMPacketC2S, PSMPacketS2C};
 
+pub mod area;
+pub mod charset;
+pub mod clock;
 pub mod config;
+pub mod ext;
 pub mod ffi;
+pub mod hooks;
 pub mod info_write;
+pub mod layout;
+pub mod manager;
 pub mod netcompat;
+pub mod pressure;
+pub mod profile;
 pub mod ptr;
+pub mod save;
+pub mod stats;
+pub mod system;
+pub mod throttle;
+pub mod window;
 
 static STATE: LazyLock<Mutex<Option<PSM>>> = LazyLock::new(|| Mutex::new(None));
 
@@ -135,6 +167,10 @@ pub fn tcp_thread() {
     }
 }
 pub fn handle_client(mut socket: TcpStream) -> color_eyre::Result<()> {
+    // every write to the client goes through its writer thread
+    let (outbox, queued) = mpsc::channel();
+    let writer = socket.try_clone()?;
+    std::thread::spawn(move || client_writer(writer, queued));
     loop {
         let mut packet_size_buf = [0u8; 4];
         socket.read_exact(&mut packet_size_buf)?;
@@ -147,14 +183,14 @@ pub fn handle_client(mut socket: TcpStream) -> color_eyre::Result<()> {
         match packet {
             PSMPacketC2S::Hi { name } => {
                 info!("Client: {}", name);
-                send_packet(
-                    &mut socket,
-                    &PSMPacketS2C::Hi {
-                        compatible: COMPATIBLE_VERSION,
-                    },
-                )?;
+                outbox.send(PSMPacketS2C::Hi {
+                    compatible: COMPATIBLE_VERSION,
+                })?;
+                let mut state = get_state_or_init().unwrap();
+                state.as_mut().unwrap().subscribers.push(outbox.clone());
             }
             PSMPacketC2S::TabletEvent {
+                device,
                 status,
                 buttons,
                 x,
@@ -162,38 +198,142 @@ pub fn handle_client(mut socket: TcpStream) -> color_eyre::Result<()> {
                 z,
                 normal_pressure,
                 tangential_pressure,
+                eraser,
+                time,
             } => {
-                let mut state = get_state_or_init().unwrap();
-                let state = state.as_mut().unwrap();
-                for (_, ctx) in state.contexts.iter_mut().filter(|(_, x)| x.enabled) {
-                    if let Err(err) = ctx.send_packet(Packet {
-                        context: ctx.handle as u32,
-                        status,
-                        time: 0,
-                        changed: 0xFFFFFFFF,
-                        serial: 0,
-                        cursor: 0,
-                        buttons,
-                        x,
-                        y,
-                        z,
-                        normal_pressure,
-                        tangential_pressure,
-                        orientation: Orientation::default(),
-                        rotation: Rotation::default(),
-                    }) {
-                        error!("Couldn't send the packet! {:?}", err);
-                    }
+                STATS.received.inc();
+                if hooks::is_playing_back() {
+                    debug!("Ignoring client packet during playback");
+                    continue;
                 }
+                let events = {
+                    let mut state = get_state_or_init().unwrap();
+                    let state = state.as_mut().unwrap();
+                    if device as usize >= state.devices.len() {
+                        warn!("Ignoring a packet from unknown device {}", device);
+                        continue;
+                    }
+                    state.eraser = eraser;
+                    let time = state.config.align_client_time.then_some(time).flatten();
+                    let time = state.clock.stamp(time, &SystemTicks);
+                    let cursor = state.active_cursor(device);
+                    let (buttons, normal_pressure) =
+                        state.apply_cursor(device, cursor, buttons, normal_pressure);
+                    state.deliver_packet(
+                        device,
+                        Packet {
+                            context: 0,
+                            status,
+                            time,
+                            changed: 0xFFFFFFFF,
+                            serial: 0,
+                            cursor,
+                            buttons,
+                            x,
+                            y,
+                            z,
+                            normal_pressure,
+                            tangential_pressure,
+                            orientation: Orientation::default(),
+                            rotation: Rotation::default(),
+                        },
+                    );
+                    std::mem::take(&mut state.cursor_events)
+                };
+                system::replay(events, &mut SendInputSink);
             }
             PSMPacketC2S::Proximity { value } => {
+                let events = {
+                    let mut state = get_state_or_init().unwrap();
+                    let state = state.as_mut().unwrap();
+                    state.proximity = value;
+                    let mut notified = Vec::new();
+                    for (_, ctx) in state.contexts.iter_mut().filter(|(_, x)| x.enabled) {
+                        // the held back packet would arrive after the pen is gone otherwise
+                        if !value
+                            && let Some(packet) = ctx.throttle.take_pending()
+                            && let Err(err) = ctx.send_packet(packet)
+                        {
+                            error!("Couldn't send the packet! {:?}", err);
+                        }
+                        if let Err(err) = ctx.proximity(value) {
+                            error!("Couldn't send the proximity update! {:?}", err);
+                        }
+                        notified.push(ctx.handle);
+                    }
+                    if !value {
+                        state.system_cursor.reset(&mut state.cursor_events);
+                    }
+                    for handle in notified {
+                        state.notify_managers(
+                            WindowMessage::Proximity,
+                            WPARAM(handle),
+                            LPARAM(if value { 0x00010001 } else { 0 }),
+                        );
+                    }
+                    std::mem::take(&mut state.cursor_events)
+                };
+                system::replay(events, &mut SendInputSink);
+            }
+            PSMPacketC2S::ExpressKey { control, pressed } => {
                 let mut state = get_state_or_init().unwrap();
                 let state = state.as_mut().unwrap();
-                for (_, ctx) in state.contexts.iter_mut().filter(|(_, x)| x.enabled) {
-                    if let Err(err) = ctx.proximity(value) {
-                        error!("Couldn't send the proximity update! {:?}", err);
-                    }
-                }
+                let location = state.config.extensions.express_keys_location;
+                state.send_extension(
+                    PKEXT_EXPKEYS2,
+                    PacketExt {
+                        exp_keys: ExpKeysData {
+                            tablet: 0,
+                            control,
+                            location,
+                            reserved: 0,
+                            state: pressed as u32,
+                        },
+                        ..Default::default()
+                    },
+                );
+            }
+            PSMPacketC2S::TouchStrip {
+                control,
+                mode,
+                position,
+            } => {
+                let mut state = get_state_or_init().unwrap();
+                let state = state.as_mut().unwrap();
+                state.send_extension(
+                    PKEXT_TOUCHSTRIP,
+                    PacketExt {
+                        touch_strip: SliderData {
+                            tablet: 0,
+                            control,
+                            mode,
+                            reserved: 0,
+                            position,
+                        },
+                        ..Default::default()
+                    },
+                );
+            }
+            PSMPacketC2S::TouchRing {
+                control,
+                mode,
+                position,
+            } => {
+                let mut state = get_state_or_init().unwrap();
+                let state = state.as_mut().unwrap();
+                state.send_extension(
+                    PKEXT_TOUCHRING,
+                    PacketExt {
+                        touch_ring: SliderData {
+                            tablet: 0,
+                            control,
+                            mode,
+                            reserved: 0,
+                            position,
+                        },
+                        ..Default::default()
+                    },
+                );
             }
             PSMPacketC2S::ConfigureContext {
                 status,
@@ -216,6 +356,7 @@ pub fn handle_client(mut socket: TcpStream) -> color_eyre::Result<()> {
                 sys_org_y,
                 sys_ext_x,
                 sys_ext_y,
+                out_of_area,
             } => {
                 let mut state = get_state_or_init().unwrap();
                 let state = state.as_mut().unwrap();
@@ -240,12 +381,16 @@ pub fn handle_client(mut socket: TcpStream) -> color_eyre::Result<()> {
                     ctx.logical_context.sys_org_y = sys_org_y;
                     ctx.logical_context.sys_ext_x = sys_ext_x;
                     ctx.logical_context.sys_ext_y = sys_ext_y;
+                    if let Some(policy) = out_of_area {
+                        ctx.out_of_area = policy;
+                    }
                     if let Err(err) = ctx.context_update() {
                         error!("Couldn't send the context update! {:?}", err);
                     }
                 }
             }
             PSMPacketC2S::ConfigureDevice {
+                device,
                 hardware,
                 packet_rate,
                 packet_mode,
@@ -262,30 +407,94 @@ pub fn handle_client(mut socket: TcpStream) -> color_eyre::Result<()> {
             } => {
                 let mut state = get_state_or_init().unwrap();
                 let state = state.as_mut().unwrap();
-                state.device.hardware = hardware;
-                state.device.packet_rate = packet_rate;
-                state.device.packet_mode = packet_mode;
-                state.device.x_margin = x_margin;
-                state.device.y_margin = y_margin;
-                state.device.z_margin = z_margin;
-                state.device.device_x = device_x.into();
-                state.device.device_y = device_y.into();
-                state.device.device_z = device_z.into();
-                state.device.normal_pressure = normal_pressure.into();
-                state.device.tangential_pressure = tangential_pressure.into();
-                state.device.orientation = orientation.map(|x| x.into());
-                state.device.rotation = rotation.map(|x| x.into());
-                for (_, ctx) in state.contexts.iter_mut().filter(|(_, x)| x.enabled) {
-                    if let Err(err) = ctx.info_update() {
-                        error!("Couldn't send the info update! {:?}", err);
-                    }
+                let before = state.info_items();
+                let Some(wti_device) = state.devices.get_mut(device as usize) else {
+                    warn!("Can't configure unknown device {}", device);
+                    continue;
+                };
+                wti_device.hardware = hardware;
+                wti_device.packet_rate = packet_rate;
+                wti_device.packet_mode = packet_mode;
+                wti_device.x_margin = x_margin;
+                wti_device.y_margin = y_margin;
+                wti_device.z_margin = z_margin;
+                wti_device.device_x = device_x.into();
+                wti_device.device_y = device_y.into();
+                wti_device.device_z = device_z.into();
+                wti_device.normal_pressure = normal_pressure.into();
+                wti_device.tangential_pressure = tangential_pressure.into();
+                wti_device.orientation = orientation.map(|x| x.into());
+                wti_device.rotation = rotation.map(|x| x.into());
+                let first = wti_device.first_cursor_type as usize;
+                let cursors = first..first + wti_device.num_cursor_types as usize;
+                let (normal, tangential) = (normal_pressure.into(), tangential_pressure.into());
+                for cursor in &mut state.cursors[cursors] {
+                    cursor.set_axes(&normal, &tangential);
+                }
+                state.notify_info_changes(&before);
+            }
+            PSMPacketC2S::ConfigureIdentity {
+                wintab_id,
+                device,
+                device_name,
+                pnp_id,
+                cursor_names,
+            } => {
+                let mut state = get_state_or_init().unwrap();
+                let state = state.as_mut().unwrap();
+                if device as usize >= state.devices.len() {
+                    warn!("Can't configure unknown device {}", device);
+                    continue;
+                }
+                let before = state.info_items();
+                if let Some(wintab_id) = wintab_id {
+                    state.config.wintab_id = wintab_id;
+                }
+                let wti_device = &mut state.devices[device as usize];
+                if let Some(name) = device_name {
+                    wti_device.name = name;
+                }
+                if let Some(pnp_id) = pnp_id {
+                    wti_device.pnp_id = pnp_id;
+                }
+                let first = wti_device.first_cursor_type as usize;
+                let cursors = first..first + wti_device.num_cursor_types as usize;
+                for (cursor, name) in state.cursors[cursors]
+                    .iter_mut()
+                    .zip(cursor_names.unwrap_or_default())
+                {
+                    cursor.name = name;
+                }
+                state.notify_info_changes(&before);
+            }
+            PSMPacketC2S::SelectProfile { name } => {
+                if profile::find(&name).is_none() {
+                    warn!("Can't select unknown profile {}", name);
+                    continue;
                 }
+                let mut state = get_state_or_init().unwrap();
+                let state = state.as_mut().unwrap();
+                let before = state.info_items();
+                state.config.profile = Some(name);
+                state.apply_config();
+                state.notify_info_changes(&before);
             }
             PSMPacketC2S::Debug { msg: _ } => {}
         }
+        hooks::flush_records();
     }
     // Ok(())
 }
+/// Writes the packets queued for one client until it's gone.
+/// Packets are queued while holding the state lock and written without it, one frame at a time.
+pub fn client_writer(mut stream: TcpStream, queued: Receiver<PSMPacketS2C>) {
+    for packet in queued {
+        if let Err(err) = send_packet(&mut stream, &packet) {
+            debug!("Client writer stopped: {:?}", err);
+            return;
+        }
+    }
+}
 pub fn send_packet(stream: &mut impl Write, packet: &PSMPacketS2C) -> color_eyre::Result<()> {
     let data = serde_json::to_vec(packet)?;
     let bytes: [u8; 4] = (data.len() as u32).to_be_bytes();
@@ -296,20 +505,60 @@ pub fn send_packet(stream: &mut impl Write, packet: &PSMPacketS2C) -> color_eyre
 
 pub struct PSM {
     pub contexts: HashMap<usize, Context>,
+    pub managers: HashMap<usize, Manager>,
+    /// Handles of the default contexts given out to managers, by (device, system).
+    pub default_handles: HashMap<(u32, bool), usize>,
+    /// Context handles in z-order, topmost first.
+    pub order: Vec<usize>,
     pub counter: usize,
-    pub default_context: WtiLogicalContext,
-    pub device: WtiDevice,
-    pub cursor: WtiCursor,
+    /// What WTInfo reports for new contexts, by (device, system).
+    /// Only changed by a manager's WTSet on the matching default context handle.
+    pub default_contexts: HashMap<(u32, bool), WtiLogicalContext>,
+    /// Connected tablets, in WTI_DEVICES order.
+    pub devices: Vec<WtiDevice>,
+    /// Cursor types of all devices, in WTI_CURSORS order.
+    pub cursors: Vec<WtiCursor>,
+    pub extensions: Vec<WtiExtension>,
+    /// Drives the Windows cursor with packets of system contexts.
+    pub system_cursor: SystemCursor,
+    /// Input from [PSM::system_cursor] that hasn't been sent yet.
+    /// Callers replay it with [SendInputSink] after releasing the state lock,
+    /// since SendInput waits on the input queue of the app under the cursor.
+    pub cursor_events: Vec<CursorEvent>,
+    /// Context [PSM::system_cursor] last followed, `None` for packets no context took.
+    pub system_cursor_context: Option<usize>,
+    /// Whether the pen is in proximity, as last reported by the client.
+    pub proximity: bool,
+    /// Whether the pen is flipped to the eraser, as last reported by the client.
+    pub eraser: bool,
+    /// Whether the normal pressure button of each device is down, see [pressure::button_pressed].
+    pub pressure_buttons: Vec<bool>,
+    /// Queues of the clients that get server notifications, see [client_writer].
+    pub subscribers: Vec<Sender<PSMPacketS2C>>,
+    /// Timestamps client packets.
+    pub clock: PacketClock,
     pub config: Config,
 }
 impl PSM {
     pub fn new(config: Config) -> Self {
         let mut state = Self {
             contexts: Default::default(),
+            managers: Default::default(),
+            default_handles: Default::default(),
+            order: Default::default(),
             counter: Default::default(),
-            default_context: WtiLogicalContext::psm_default(),
-            device: WtiDevice::psm_default(),
-            cursor: WtiCursor::psm_default(),
+            default_contexts: Default::default(),
+            devices: Vec::new(),
+            cursors: Vec::new(),
+            extensions: Vec::new(),
+            system_cursor: SystemCursor::default(),
+            cursor_events: Vec::new(),
+            system_cursor_context: None,
+            proximity: true,
+            eraser: false,
+            pressure_buttons: Vec::new(),
+            subscribers: Vec::new(),
+            clock: PacketClock::default(),
             config,
         };
         state.apply_config();
@@ -317,51 +566,510 @@ impl PSM {
     }
 
     fn apply_config(&mut self) {
-        self.default_context.status = self.config.preset.status;
-        self.default_context.packet_rate = self.config.preset.packet_rate;
-        self.default_context.packet_mode = self.config.preset.packet_mode;
-        self.default_context.move_mask = self.config.preset.move_mask;
-        self.default_context.in_org_x = self.config.preset.in_org_x;
-        self.default_context.in_org_y = self.config.preset.in_org_y;
-        self.default_context.in_org_z = self.config.preset.in_org_z;
-        self.default_context.in_ext_x = self.config.preset.in_ext_x;
-        self.default_context.in_ext_y = self.config.preset.in_ext_y;
-        self.default_context.in_ext_z = self.config.preset.in_ext_z;
-        self.default_context.out_org_x = self.config.preset.out_org_x;
-        self.default_context.out_org_y = self.config.preset.out_org_y;
-        self.default_context.out_org_z = self.config.preset.out_org_z;
-        self.default_context.out_ext_x = self.config.preset.out_ext_x;
-        self.default_context.out_ext_y = self.config.preset.out_ext_y;
-        self.default_context.out_ext_z = self.config.preset.out_ext_z;
-        self.default_context.sys_org_x = self.config.preset.sys_org_x;
-        self.default_context.sys_org_y = self.config.preset.sys_org_y;
-        self.default_context.sys_ext_x = self.config.preset.sys_ext_x;
-        self.default_context.sys_ext_y = self.config.preset.sys_ext_y;
-        self.device.hardware = self.config.preset.hardware;
-        self.device.packet_rate = self.config.preset.packet_rate;
-        self.device.packet_mode = self.config.preset.packet_mode;
-        self.device.x_margin = self.config.preset.x_margin;
-        self.device.y_margin = self.config.preset.y_margin;
-        self.device.z_margin = self.config.preset.z_margin;
-        self.device.device_x = self.config.preset.device_x.into();
-        self.device.device_y = self.config.preset.device_y.into();
-        self.device.device_z = self.config.preset.device_z.into();
-        self.device.normal_pressure = self.config.preset.normal_pressure.into();
-        self.device.tangential_pressure = self.config.preset.tangential_pressure.into();
-        self.device.orientation = self.config.preset.orientation.map(|x| x.into());
-        self.device.rotation = self.config.preset.rotation.map(|x| x.into());
+        if let Some(name) = &self.config.profile {
+            match profile::find(name) {
+                Some(profile) => profile.apply(&mut self.config),
+                None => warn!("Unknown profile {}, using the preset", name),
+            }
+        }
+        self.extensions = WtiExtension::psm_extensions(&self.config.extensions);
+        self.devices.clear();
+        self.cursors.clear();
+        self.default_contexts.clear();
+        for (i, preset) in self.config.device_presets().enumerate() {
+            let mut device = WtiDevice::psm_default();
+            device.name = match &preset.name {
+                Some(name) => name.clone(),
+                None => format!("PSMDEV{}", i + 1),
+            };
+            device.pnp_id = preset.pnp_id.clone();
+            device.hardware = preset.hardware;
+            device.packet_rate = preset.packet_rate;
+            device.packet_mode = preset.packet_mode;
+            device.x_margin = preset.x_margin;
+            device.y_margin = preset.y_margin;
+            device.z_margin = preset.z_margin;
+            device.device_x = preset.device_x.into();
+            device.device_y = preset.device_y.into();
+            device.device_z = preset.device_z.into();
+            device.normal_pressure = preset.normal_pressure.into();
+            device.tangential_pressure = preset.tangential_pressure.into();
+            device.orientation = preset.orientation.map(|x| x.into());
+            device.rotation = preset.rotation.map(|x| x.into());
+            // each device's cursors follow the previous device's
+            for system in [false, true] {
+                let lc = WtiLogicalContext::from_preset(preset, i as u32, system);
+                self.default_contexts.insert((i as u32, system), lc);
+            }
+            device.first_cursor_type = self.cursors.len() as u32;
+            device.num_cursor_types = preset.cursors.len() as u32;
+            self.cursors.extend(
+                preset.cursors.iter().map(|x| {
+                    WtiCursor::new(x, &device.normal_pressure, &device.tangential_pressure)
+                }),
+            );
+            self.devices.push(device);
+        }
+        self.pressure_buttons = vec![false; self.devices.len()];
+    }
+
+    /// WTI_INTERFACE, with the number of configured devices and cursors.
+    pub fn interface(&self) -> WtiInterface {
+        WtiInterface {
+            wintabid: self.config.wintab_id.clone(),
+            num_devices: self.devices.len() as u32,
+            num_cursors: self.cursors.len() as u32,
+            ..WtiInterface::psm_default()
+        }
+    }
+
+    /// Cursor type of the pen on `device`, or of its eraser while [PSM::eraser] is set.
+    pub fn active_cursor(&self, device: u32) -> u32 {
+        let Some(device) = self.devices.get(device as usize) else {
+            return 0;
+        };
+        let first = device.first_cursor_type;
+        let inverted = if self.eraser { CRC_INVERT } else { 0 };
+        (first..first + device.num_cursor_types)
+            .find(|x| {
+                let cursor = &self.cursors[*x as usize];
+                cursor.packet_data & PK_NORMAL_PRESSURE != 0
+                    && cursor.capabilities & CRC_INVERT == inverted
+            })
+            .unwrap_or(first)
+    }
+
+    /// Applies a cursor type's pressure curve and button map to the buttons and normal pressure
+    /// of a client packet. The normal pressure button follows the cursor's button marks.
+    pub fn apply_cursor(
+        &mut self,
+        device: u32,
+        cursor: u32,
+        buttons: u32,
+        pressure: u32,
+    ) -> (u32, u32) {
+        let Some(csr) = self.cursors.get(cursor as usize) else {
+            return (buttons, pressure);
+        };
+        if csr.packet_data & PK_NORMAL_PRESSURE == 0 {
+            return (csr.logical_buttons(buttons), pressure);
+        }
+        let pressed = &mut self.pressure_buttons[device as usize];
+        *pressed = pressure::button_pressed(*pressed, pressure, csr.npbtnmarks);
+        let bit = 1 << (csr.physical_button & 31);
+        let buttons = if *pressed {
+            buttons | bit
+        } else {
+            buttons & !bit
+        };
+        (csr.logical_buttons(buttons), csr.pressure.apply(pressure))
+    }
+
+    /// CSR_SYSBTNMAP of a cursor type, no actions for unknown ones.
+    pub fn system_button_map(&self, cursor: u32) -> [u8; 32] {
+        self.cursors
+            .get(cursor as usize)
+            .map_or([SBN_NONE; 32], |x| x.system_button_map)
+    }
+
+    /// Registers a new context owned by `hwnd`, returning its handle.
+    /// Returns 0 if the context asks for a device that doesn't exist.
+    pub fn open_context(&mut self, hwnd: HWND, mut context: Context) -> usize {
+        let device = context.logical_context.device;
+        if device as usize >= self.devices.len() {
+            error!("Can't open a context on unknown device {}", device);
+            return 0;
+        }
+        self.counter += 1;
+        let handle = self.counter;
+
+        context.handle = handle;
+        context.window = ThreadHWND(hwnd);
+        context.out_of_area = self.config.out_of_area;
+        // new contexts are opened on top, so raising them only updates the others
+        context.logical_context.status = if context.enabled {
+            CXS_ONTOP
+        } else {
+            CXS_DISABLED
+        };
+        let enabled = context.enabled;
+        self.contexts.insert(handle, context);
+        self.raise(handle);
+        // WT_CTXOPEN carries the status after ordering
+        let context = self.contexts.get_mut(&handle).unwrap();
+        if let Err(err) = context.context_open() {
+            error!("Couldn't send the context open message! {:?}", err);
+        }
+        let status = context.logical_context.status;
+        self.notify_managers(
+            WindowMessage::CtxOpen,
+            WPARAM(handle),
+            LPARAM(status as isize),
+        );
+        debug!(
+            "new context registered at {} (enabled = {})",
+            handle, enabled
+        );
+
+        handle
+    }
+
+    /// Moves the context to the top of the overlap order.
+    pub fn raise(&mut self, handle: usize) {
+        self.order.retain(|x| *x != handle);
+        self.order.insert(0, handle);
+        self.update_overlap();
+    }
+
+    /// Moves the context to the bottom of the overlap order.
+    pub fn lower(&mut self, handle: usize) {
+        self.order.retain(|x| *x != handle);
+        self.order.push(handle);
+        self.update_overlap();
+    }
+
+    /// Sends a pen packet from `device` to the topmost context opened on it.
+    /// System context input is left in [PSM::cursor_events].
+    pub fn deliver_packet(&mut self, device: u32, mut packet: Packet) {
+        self.reap_orphans(&Win32Windows);
+        self.sync_foreground();
+        let mode = self.config.throttle;
+        let Some(handle) = self.top_context(device).map(|x| x.handle) else {
+            self.move_system_cursor(device, None, &packet);
+            return;
+        };
+        packet.status = self.packet_status(&packet);
+        let ctx = self.contexts.get_mut(&handle).unwrap();
+        if !ctx.fit_packet(&self.devices[device as usize], &mut packet) {
+            debug!("Dropping packet outside of context {}", handle);
+            STATS.dropped_out_of_range.inc();
+            return;
+        }
+        let rate = ctx.logical_context.packet_rate;
+        let Some(mut packet) = ctx.throttle.push(packet, rate, mode, &SystemClock) else {
+            return;
+        };
+        packet.context = ctx.handle as u32;
+        if let Err(err) = ctx.send_packet(packet.clone()) {
+            error!("Couldn't send the packet! {:?}", err);
+        }
+        let handle = packet.context as usize;
+        self.move_system_cursor(device, Some(handle), &packet);
+    }
+
+    /// Status flags of a packet about to be sent to the context.
+    /// Only the bits the client can know about are taken from it, [TPS_QUEUE_ERR] is set when queueing.
+    /// [TPS_MARGIN] comes from [Context::fit_packet].
+    pub fn packet_status(&self, packet: &Packet) -> u32 {
+        let mut status = packet.status & CLIENT_STATUS_MASK;
+        if !self.proximity {
+            status |= TPS_PROXIMITY;
+        }
+        if self.eraser {
+            status |= TPS_INVERT;
+        }
+        status
+    }
+
+    /// Moves the Windows cursor if the packet went to a system context,
+    /// or to no context (`None`) with [without_context](config::SystemCursorConfig::without_context).
+    pub fn move_system_cursor(&mut self, device: u32, handle: Option<usize>, packet: &Packet) {
+        let config = &self.config.system_cursor;
+        if !config.enabled {
+            return;
+        }
+        let map = self.system_button_map(packet.cursor);
+        let lc = match handle {
+            Some(handle) => self
+                .contexts
+                .get(&handle)
+                .map(|x| &x.logical_context)
+                .filter(|x| x.options & CXO_SYSTEM != 0),
+            None if config.without_context => self.default_contexts.get(&(device, true)),
+            None => None,
+        };
+        let Some(lc) = lc else {
+            self.system_cursor.reset(&mut self.cursor_events);
+            return;
+        };
+        // held buttons and the last position don't carry over to another context
+        if self.system_cursor_context != handle {
+            self.system_cursor.reset(&mut self.cursor_events);
+            self.system_cursor_context = handle;
+        }
+        self.system_cursor
+            .update(lc, packet, &map, &mut self.cursor_events);
+    }
+
+    /// Topmost context that can receive pen packets from `device`.
+    pub fn top_context(&mut self, device: u32) -> Option<&mut Context> {
+        let handle = *self.order.iter().find(|x| {
+            self.contexts.get(x).is_some_and(|ctx| {
+                ctx.enabled && !ctx.window.0.0.is_null() && ctx.logical_context.device == device
+            })
+        })?;
+        self.contexts.get_mut(&handle)
+    }
+
+    /// Sends an extension packet to the topmost context that selected the extension.
+    pub fn send_extension(&mut self, mask: u32, packet: PacketExt) {
+        self.reap_orphans(&Win32Windows);
+        let handle = self.order.iter().copied().find(|x| {
+            self.contexts.get(x).is_some_and(|ctx| {
+                ctx.enabled
+                    && !ctx.window.0.0.is_null()
+                    && ctx.logical_context.packet_data & mask != 0
+            })
+        });
+        let Some(ctx) = handle.and_then(|x| self.contexts.get_mut(&x)) else {
+            debug!("no context selected extension {:#x}, dropping", mask);
+            return;
+        };
+        if let Err(err) = ctx.send_packet_ext(packet) {
+            error!("Couldn't send the extension packet! {:?}", err);
+        }
+    }
+
+    /// Removes a context, posting WT_CTXCLOSE to it (if `post`) and to the managers.
+    pub fn close_context(&mut self, handle: usize, post: bool) -> Option<Context> {
+        let mut ctx = self.contexts.remove(&handle)?;
+        if post && let Err(err) = ctx.context_close() {
+            error!("Couldn't send the context close message! {:?}", err);
+        }
+        self.notify_managers(
+            WindowMessage::CtxClose,
+            WPARAM(handle),
+            LPARAM(ctx.logical_context.status as isize),
+        );
+        self.default_handles.retain(|_, x| *x != handle);
+        self.order.retain(|i| *i != handle);
+        self.update_overlap();
+        Some(ctx)
+    }
+
+    /// Closes contexts whose window was destroyed without WTClose, returning their handles.
+    pub fn reap_orphans(&mut self, windows: &impl WindowTracker) -> Vec<usize> {
+        let orphans = self
+            .contexts
+            .values()
+            .filter(|ctx| !ctx.window.0.0.is_null() && !windows.is_alive(ctx.window.0))
+            .map(|ctx| ctx.handle)
+            .collect::<Vec<_>>();
+        for handle in orphans.iter() {
+            warn!(
+                "Window of context {} was destroyed without WTClose, closing it",
+                handle
+            );
+            self.close_context(*handle, false);
+            self.notify_clients(&PSMPacketS2C::ContextReaped {
+                handle: *handle as u64,
+            });
+        }
+        orphans
+    }
+
+    /// Queues a packet for every connected client, forgetting the ones that are gone.
+    pub fn notify_clients(&mut self, packet: &PSMPacketS2C) {
+        self.subscribers
+            .retain(|outbox| match outbox.send(packet.clone()) {
+                Ok(_) => true,
+                Err(_) => {
+                    debug!("Dropping a client subscriber");
+                    false
+                }
+            });
+    }
+
+    /// Raises the topmost context owned by the foreground window, if it isn't on top already.
+    pub fn sync_foreground(&mut self) {
+        let foreground = unsafe { GetForegroundWindow() };
+        if foreground.0.is_null() {
+            return;
+        }
+        let active = self.order.iter().copied().find(|x| {
+            self.contexts.get(x).is_some_and(|ctx| {
+                !ctx.window.0.0.is_null()
+                    && unsafe { GetAncestor(ctx.window.0, GA_ROOT) } == foreground
+            })
+        });
+        if let Some(handle) = active
+            && self.order.first() != Some(&handle)
+        {
+            debug!("context {} was activated, raising it", handle);
+            self.raise(handle);
+        }
+    }
+
+    /// Recomputes [CXS_ONTOP] and [CXS_OBSCURED] from the overlap order
+    /// and posts WT_CTXOVERLAP to every context whose status changed.
+    pub fn update_overlap(&mut self) {
+        let mut changed = Vec::new();
+        let mut top_found = false;
+        for handle in self.order.iter() {
+            let Some(ctx) = self.contexts.get_mut(handle) else {
+                continue;
+            };
+            let old_status = ctx.logical_context.status;
+            let mut status = old_status & !(CXS_ONTOP | CXS_OBSCURED | CXS_DISABLED);
+            if !ctx.enabled {
+                status |= CXS_DISABLED;
+            } else if top_found {
+                status |= CXS_OBSCURED;
+            } else {
+                status |= CXS_ONTOP;
+                top_found = true;
+            }
+            ctx.logical_context.status = status;
+            if (status ^ old_status) & (CXS_ONTOP | CXS_OBSCURED) == 0 {
+                continue;
+            }
+            if let Err(err) = ctx.overlap_update() {
+                error!("Couldn't send the overlap update! {:?}", err);
+            }
+            changed.push((*handle, status));
+        }
+        for (handle, status) in changed {
+            self.notify_managers(
+                WindowMessage::CtxOverlap,
+                WPARAM(handle),
+                LPARAM(status as isize),
+            );
+        }
+    }
+
+    /// Every item of the categories PSM can change at runtime, by (category, index).
+    /// Taken before a change and passed to [PSM::notify_info_changes] after it.
+    pub fn info_items(&self) -> BTreeMap<(u32, u32), Vec<u8>> {
+        let mut items = BTreeMap::new();
+        let mut add = |category: u32, info: &dyn Fn(u32) -> Option<Vec<u8>>, count: u32| {
+            for index in 1..=count {
+                if let Some(item) = info(index) {
+                    items.insert((category, index), item);
+                }
+            }
+        };
+        let interface = self.interface();
+        add(
+            WTI_INTERFACE,
+            &|x| interface.item(x, Charset::Wide),
+            WtiInterface::ITEMS,
+        );
+        for (i, device) in self.devices.iter().enumerate() {
+            let category = WTI_DEVICES + i as u32;
+            add(
+                category,
+                &|x| device.item(x, Charset::Wide),
+                WtiDevice::ITEMS,
+            );
+        }
+        for (i, cursor) in self.cursors.iter().enumerate() {
+            let category = WTI_CURSORS + i as u32;
+            add(
+                category,
+                &|x| cursor.item(x, Charset::Wide),
+                WtiCursor::ITEMS,
+            );
+        }
+        items
+    }
+
+    /// Posts one WT_INFOCHANGE per item that differs from `before` to enabled contexts and managers.
+    pub fn notify_info_changes(&mut self, before: &BTreeMap<(u32, u32), Vec<u8>>) {
+        let changes = info_changes(before, &self.info_items());
+        for (category, index) in changes {
+            debug!("WTInfo({}, {}) changed", category, index);
+            for (_, ctx) in self.contexts.iter_mut().filter(|(_, x)| x.enabled) {
+                if let Err(err) = ctx.info_change(category, index) {
+                    error!("Couldn't send the info update! {:?}", err);
+                }
+            }
+            let lparam = info_change_lparam(category, index);
+            self.notify_managers(WindowMessage::InfoChange, WPARAM(0), lparam);
+        }
     }
+
+    /// Posts a message to every manager window.
+    pub fn notify_managers(&self, message: WindowMessage, wparam: WPARAM, lparam: LPARAM) {
+        for manager in self.managers.values() {
+            if let Err(err) = manager.post(message, wparam, lparam) {
+                error!(
+                    "Couldn't send {:?} to manager {}! {:?}",
+                    message, manager.handle, err
+                );
+            }
+        }
+    }
+
+    /// Returns the handle of the default context for the device, creating it on first use.
+    /// Default contexts are never enabled and have no owner window.
+    pub fn default_context_handle(&mut self, device: u32, system: bool) -> Option<usize> {
+        let logical_context = self.default_contexts.get(&(device, system))?.clone();
+        if let Some(handle) = self.default_handles.get(&(device, system)) {
+            return Some(*handle);
+        }
+        self.counter += 1;
+        let handle = self.counter;
+        let mut context = Context::new(handle, false);
+        context.logical_context = logical_context;
+        context.logical_context.status = CXS_DISABLED;
+        self.contexts.insert(handle, context);
+        self.default_handles.insert((device, system), handle);
+        Some(handle)
+    }
+
+    /// (device, system) of the handle if it's one of the default contexts given out to managers.
+    pub fn default_context_key(&self, handle: usize) -> Option<(u32, bool)> {
+        self.default_handles
+            .iter()
+            .find(|(_, x)| **x == handle)
+            .map(|(key, _)| *key)
+    }
+}
+
+/// Items that were added, removed or changed between two [PSM::info_items] snapshots, in order.
+pub fn info_changes(
+    before: &BTreeMap<(u32, u32), Vec<u8>>,
+    after: &BTreeMap<(u32, u32), Vec<u8>>,
+) -> Vec<(u32, u32)> {
+    let mut changes = before
+        .iter()
+        .filter(|(key, value)| after.get(*key) != Some(*value))
+        .map(|(key, _)| *key)
+        .chain(after.keys().filter(|x| !before.contains_key(*x)).copied())
+        .collect::<Vec<_>>();
+    changes.sort();
+    changes
+}
+
+/// WT_INFOCHANGE's lParam, the category in the low word and the index in the high word.
+pub fn info_change_lparam(category: u32, index: u32) -> LPARAM {
+    LPARAM(((index & 0xFFFF) << 16 | category & 0xFFFF) as isize)
 }
 
+/// Largest packet queue [WTQueueSizeSet] will allocate.
+pub const MAX_QUEUE_SIZE: usize = 8192;
+
 pub struct Context {
     pub handle: usize,
     pub enabled: bool,
     pub window: ThreadHWND,
     pub logical_context: WtiLogicalContext,
     pub packets: VecDeque<Packet>,
+    pub ext_packets: VecDeque<PacketExt>,
+    /// Values of TABLET_PROPERTY_OVERRIDE set through WTExtSet, by (extension tag, control, function).
+    pub ext_overrides: HashMap<(u32, u8, u8), u32>,
     pub queue_size: usize,
+    /// Set when packets were lost to a queue overflow, cleared by the next queued packet.
+    pub overflowed: bool,
+    /// Cursor type of the last packet, used for WT_CSRCHANGE.
+    pub cursor: Option<u32>,
+    /// Holds packets back to the context's packet rate.
+    pub throttle: Throttle,
+    /// What happens to points outside of the output area.
+    pub out_of_area: OutOfAreaPolicy,
     pub serial: usize,
-    pub time: Instant,
+    /// Last queued packet before [to_relative], for the deltas of relative mode (lcPktMode).
+    pub last_packet: Option<Packet>,
 }
 impl Context {
     pub fn new(handle: usize, enabled: bool) -> Self {
@@ -371,9 +1079,15 @@ impl Context {
             window: ThreadHWND::default(),
             logical_context: WtiLogicalContext::psm_default(),
             packets: VecDeque::new(),
+            ext_packets: VecDeque::new(),
+            ext_overrides: HashMap::new(),
             queue_size: 1024,
+            overflowed: false,
+            cursor: None,
+            throttle: Throttle::default(),
+            out_of_area: OutOfAreaPolicy::default(),
             serial: 0,
-            time: Instant::now(),
+            last_packet: None,
         }
     }
 
@@ -384,35 +1098,99 @@ impl Context {
         if self.window.0.0.is_null() {
             bail!("packet sent without a valid window");
         }
-        if (packet.x as i32) > self.logical_context.out_ext_x
-            || (packet.y as i32) > self.logical_context.out_ext_y
-            || (packet.x as i32) < self.logical_context.out_org_x
-            || (packet.y as i32) < self.logical_context.out_org_y {
-                warn!("Ignoring packet with out of range coordinates! You might need to check your psm.json.");
-                return Ok(());
+        if self.queue_size == 0 {
+            bail!("packet sent when context has no queue");
         }
         self.serial += 1;
         packet.context = self.handle as u32;
         packet.serial = self.serial as u32;
         packet.orientation.altitude = 900;
-        packet.time = self.time.elapsed().as_millis() as u32;
+        // relative mode reports the change since the previous packet
+        let absolute = packet.clone();
+        let packet_mode = self.logical_context.packet_mode;
+        to_relative(&mut packet, self.last_packet.as_ref(), packet_mode);
+        self.last_packet = Some(absolute);
         debug!("wtpacket: {:?}", packet);
+        hooks::feed_record(|| self.packet_layout().to_bytes(&packet));
         // limiting by queue size
-        let queue_size = self.queue_size.max(1) as isize;
-        for _overflow in 0..((self.packets.len() as isize) - queue_size + 1) {
+        while self.packets.len() >= self.queue_size {
             self.packets.pop_front();
+            self.overflowed = true;
+            STATS.dropped_overflow.inc();
         }
+        // the first packet after a loss reports it
+        if self.overflowed {
+            packet.status |= TPS_QUEUE_ERR;
+            self.overflowed = false;
+        }
+        let cursor = packet.cursor;
         self.packets.push_back(packet);
-        // posting WT_PACKET(serial, ctx_handle)
-        unsafe {
-            PostMessageW(
-                Some(self.window.0),
-                WindowMessage::Packet.value(self.logical_context.msg_base),
+        STATS.packet_delivered();
+        // posting WT_CSRCHANGE(serial, ctx_handle)
+        if self.cursor.replace(cursor) != Some(cursor) {
+            self.post(
+                WindowMessage::CsrChange,
                 WPARAM(self.serial),
                 LPARAM(self.handle as isize),
-            )?
-        };
-        Ok(())
+            )?;
+        }
+        // posting WT_PACKET(serial, ctx_handle)
+        self.post(
+            WindowMessage::Packet,
+            WPARAM(self.serial),
+            LPARAM(self.handle as isize),
+        )
+    }
+
+    pub fn send_packet_ext(&mut self, mut packet: PacketExt) -> color_eyre::Result<()> {
+        if !self.enabled {
+            bail!("packet sent when context is disabled");
+        }
+        if self.window.0.0.is_null() {
+            bail!("packet sent without a valid window");
+        }
+        if self.queue_size == 0 {
+            bail!("packet sent when context has no queue");
+        }
+        self.serial += 1;
+        packet.base.context = self.handle;
+        packet.base.serial = self.serial as u32;
+        packet.base.time = SystemTicks.ticks();
+        debug!("wtpacketext: {:?}", packet);
+        while self.ext_packets.len() >= self.queue_size {
+            self.ext_packets.pop_front();
+        }
+        self.ext_packets.push_back(packet);
+        // posting WT_PACKETEXT(serial, ctx_handle)
+        self.post(
+            WindowMessage::PacketExt,
+            WPARAM(self.serial),
+            LPARAM(self.handle as isize),
+        )
+    }
+
+    pub fn context_open(&mut self) -> color_eyre::Result<()> {
+        if self.window.0.0.is_null() {
+            bail!("update sent without a valid window");
+        }
+        // posting WT_CTXOPEN(ctx_handle, status)
+        self.post(
+            WindowMessage::CtxOpen,
+            WPARAM(self.handle),
+            LPARAM(self.logical_context.status as isize),
+        )
+    }
+
+    pub fn context_close(&mut self) -> color_eyre::Result<()> {
+        if self.window.0.0.is_null() {
+            bail!("update sent without a valid window");
+        }
+        // posting WT_CTXCLOSE(ctx_handle, status)
+        self.post(
+            WindowMessage::CtxClose,
+            WPARAM(self.handle),
+            LPARAM(self.logical_context.status as isize),
+        )
     }
 
     pub fn context_update(&mut self) -> color_eyre::Result<()> {
@@ -420,31 +1198,78 @@ impl Context {
             bail!("update sent without a valid window");
         }
         // posting WT_CTXUPDATE(ctx_handle, status)
-        unsafe {
-            PostMessageW(
-                Some(self.window.0),
-                WindowMessage::CtxUpdate.value(self.logical_context.msg_base),
-                WPARAM(self.handle),
-                LPARAM(if self.enabled { CXS_DISABLED } else { 0 } as isize),
-            )?
-        };
-        Ok(())
+        self.post(
+            WindowMessage::CtxUpdate,
+            WPARAM(self.handle),
+            LPARAM(self.logical_context.status as isize),
+        )
     }
 
-    pub fn info_update(&mut self) -> color_eyre::Result<()> {
+    pub fn overlap_update(&mut self) -> color_eyre::Result<()> {
         if self.window.0.0.is_null() {
             bail!("update sent without a valid window");
         }
-        // posting WT_INFOCHANGE(0, categoryAndIndex)
-        unsafe {
-            PostMessageW(
-                Some(self.window.0),
-                WindowMessage::InfoChange.value(self.logical_context.msg_base),
-                WPARAM(0),
-                LPARAM(0x10004),
-            )?;
+        // posting WT_CTXOVERLAP(ctx_handle, status)
+        self.post(
+            WindowMessage::CtxOverlap,
+            WPARAM(self.handle),
+            LPARAM(self.logical_context.status as isize),
+        )
+    }
+
+    /// Tells the app that WTInfo(`category`, `index`) changed.
+    pub fn info_change(&mut self, category: u32, index: u32) -> color_eyre::Result<()> {
+        if self.window.0.0.is_null() {
+            bail!("update sent without a valid window");
+        }
+        // posting WT_INFOCHANGE(0, MAKELPARAM(category, index)), zero for changes from the hardware
+        self.post(
+            WindowMessage::InfoChange,
+            WPARAM(0),
+            info_change_lparam(category, index),
+        )
+    }
+
+    /// How this context's packets are laid out in the app's buffers.
+    pub fn packet_layout(&self) -> PacketLayout {
+        PacketLayout::native(
+            self.logical_context.packet_data,
+            self.logical_context.packet_mode,
+        )
+    }
+
+    /// Copies the packet with `serial` to `ptr` and discards it with the older ones, like WTPacket.
+    /// Returns false if there's no such packet.
+    ///
+    /// # Safety
+    /// `ptr` must be null or valid for writing one packet in the context's layout.
+    pub unsafe fn take_packet(&mut self, serial: u32, ptr: *mut c_void) -> bool {
+        // serials are shared, so a serial from WT_PACKETEXT is only in the extension queue
+        if let Some(packet) = self.ext_packets.iter().find(|x| x.base.serial == serial) {
+            // a null buffer only discards the packets
+            if !ptr.is_null() {
+                packet.write(ptr, self.logical_context.packet_data);
+            }
+            self.ext_packets.retain_mut(|x| x.base.serial > serial);
+            return true;
+        }
+        self.packets.retain_mut(|x| x.serial >= serial);
+        let packet = match self.packets.iter().find(|x| x.serial == serial) {
+            Some(x) => x,
+            None => return false,
         };
-        Ok(())
+        if !ptr.is_null() {
+            unsafe { self.packet_layout().write_ptr([packet], ptr) };
+        }
+        self.packets.retain_mut(|x| x.serial > serial);
+        true
+    }
+
+    /// Fits a packet to the output area with the device's margins and the context's
+    /// out-of-area policy, see [area::fit_packet]. Returns false if it should be dropped.
+    pub fn fit_packet(&self, device: &WtiDevice, packet: &mut Packet) -> bool {
+        let margins = area::margins(&self.logical_context, device);
+        area::fit_packet(&self.logical_context, margins, self.out_of_area, packet)
     }
 
     pub fn proximity(&mut self, value: bool) -> color_eyre::Result<()> {
@@ -455,15 +1280,29 @@ impl Context {
             bail!("packet sent without a valid window");
         }
         // posting WT_PROXIMITY(ctx_handle, value)
-        unsafe {
-            PostMessageW(
-                Some(self.window.0),
-                WindowMessage::Proximity.value(self.logical_context.msg_base),
-                WPARAM(self.handle),
-                LPARAM(if value { 0x00010001 } else { 0 }),
-            )?
-        };
-        Ok(())
+        self.post(
+            WindowMessage::Proximity,
+            WPARAM(self.handle),
+            LPARAM(if value { 0x00010001 } else { 0 }),
+        )
+    }
+
+    /// Posts a message to the owner window, unless the context options opt out of it.
+    fn post(
+        &self,
+        message: WindowMessage,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> color_eyre::Result<()> {
+        if !message.is_posted(self.logical_context.options) {
+            return Ok(());
+        }
+        post_message(
+            self.window.0,
+            message.value(self.logical_context.msg_base),
+            wparam,
+            lparam,
+        )
     }
 }
 
@@ -472,57 +1311,54 @@ pub struct ThreadHWND(pub HWND);
 unsafe impl Send for ThreadHWND {}
 unsafe impl Sync for ThreadHWND {}
 
+/// # Safety
+/// `lp_log_ctx` must point to a valid LOGCONTEXTA.
 #[unsafe(no_mangle)]
 pub unsafe extern "C-unwind" fn WTOpenA(
     hwnd: HWND,
-    lp_log_ctx: *mut WtiLogicalContext,
+    lp_log_ctx: *mut LogContextA,
     f_enable: bool,
 ) -> usize {
     debug!("WTOpenA({:#?}, {:#?}, {})", hwnd, lp_log_ctx, f_enable);
-    unsafe { WTOpen(hwnd, lp_log_ctx, f_enable) }
+    unsafe { open(hwnd, lp_log_ctx as *const c_void, Charset::Ansi, f_enable) }
 }
+/// # Safety
+/// `lp_log_ctx` must point to a valid LOGCONTEXTW.
 #[unsafe(no_mangle)]
 pub unsafe extern "C-unwind" fn WTOpenW(
     hwnd: HWND,
-    lp_log_ctx: *mut WtiLogicalContext,
+    lp_log_ctx: *mut LogContextW,
     f_enable: bool,
 ) -> usize {
     debug!("WTOpenW({:#?}, {:#?}, {})", hwnd, lp_log_ctx, f_enable);
-    unsafe { WTOpen(hwnd, lp_log_ctx, f_enable) }
+    unsafe { open(hwnd, lp_log_ctx as *const c_void, Charset::Wide, f_enable) }
 }
+/// # Safety
+/// `lp_log_ctx` must point to a valid LOGCONTEXTW.
 #[unsafe(no_mangle)]
 pub unsafe extern "C-unwind" fn WTOpen(
     hwnd: HWND,
-    lp_log_ctx: *mut WtiLogicalContext,
+    lp_log_ctx: *mut LogContextW,
     f_enable: bool,
 ) -> usize {
     debug!("WTOpen({:#?}, {:#?}, {})", hwnd, lp_log_ctx, f_enable);
+    unsafe { open(hwnd, lp_log_ctx as *const c_void, Charset::Wide, f_enable) }
+}
+/// # Safety
+/// `lp_log_ctx` must be null or point to a valid LOGCONTEXTA or LOGCONTEXTW, depending on `charset`.
+pub unsafe fn open(hwnd: HWND, lp_log_ctx: *const c_void, charset: Charset, enable: bool) -> usize {
     if lp_log_ctx.is_null() {
         error!("WTOpen lp_log_ctx is null");
         return 0;
     }
-    unsafe {
-        debug!("LogContext -> {:#?}", *lp_log_ctx);
-    }
+    let logical_context = unsafe { WtiLogicalContext::read_ptr(lp_log_ctx, charset) };
+    debug!("LogContext -> {:#?}", logical_context);
 
     let mut state = get_state_or_init().unwrap();
     let state = state.as_mut().unwrap();
-    state.counter += 1;
-
-    let handle = state.counter;
-
-    let mut context = Context::new(handle, f_enable);
-    context.window = ThreadHWND(hwnd);
-    unsafe {
-        std::ptr::copy(lp_log_ctx, &mut context.logical_context, 1);
-    }
-    state.contexts.insert(handle, context);
-    debug!(
-        "new context registered at {} (enabled = {})",
-        handle, f_enable
-    );
-
-    handle
+    let mut context = Context::new(0, enable);
+    context.logical_context = logical_context;
+    state.open_context(hwnd, context)
 }
 
 #[unsafe(no_mangle)]
@@ -530,22 +1366,49 @@ pub extern "C-unwind" fn WTEnable(ctx_id: usize, enable: bool) -> bool {
     debug!("WTEnable({:#?}, {})", ctx_id, enable);
     let mut state = get_state_or_init().unwrap();
     let state = state.as_mut().unwrap();
+    // default contexts only describe WTInfo defaults to managers, they never get packets
+    if state.default_context_key(ctx_id).is_some() {
+        error!(
+            "WTEnable({:#?}) failed! default contexts can't be enabled",
+            ctx_id
+        );
+        return false;
+    }
     let ctx = match state.contexts.get_mut(&ctx_id) {
         Some(ctx) => ctx,
         None => return false,
     };
     ctx.enabled = enable;
+    state.update_overlap();
+    let Some(ctx) = state.contexts.get_mut(&ctx_id) else {
+        return false;
+    };
+    if let Err(err) = ctx.context_update() {
+        error!("Couldn't send the context update! {:?}", err);
+    }
+    let status = ctx.logical_context.status;
+    state.notify_managers(
+        WindowMessage::CtxUpdate,
+        WPARAM(ctx_id),
+        LPARAM(status as isize),
+    );
     true
 }
 
 // https://developer-docs.wacom.com/docs/icbt/windows/wintab/wintab-reference/#wtpacketsget
+/// # Safety
+/// `ptr` must be null or valid for writing `max_packets` packets in the context's layout.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTPacketsGet(ctx_id: usize, max_packets: i32, ptr: *mut c_void) -> u32 {
+pub unsafe extern "C-unwind" fn WTPacketsGet(
+    ctx_id: usize,
+    max_packets: i32,
+    ptr: *mut c_void,
+) -> u32 {
     debug!(
         "WTPacketsGet({:#?}, {:#?}, {:#?})",
         ctx_id, max_packets, ptr
     );
-    match packets_get(ctx_id, max_packets, ptr) {
+    match unsafe { packets_get(ctx_id, max_packets, ptr) } {
         Ok(v) => v,
         Err(err) => {
             error!(
@@ -557,7 +1420,13 @@ pub extern "C-unwind" fn WTPacketsGet(ctx_id: usize, max_packets: i32, ptr: *mut
         }
     }
 }
-pub fn packets_get(ctx_id: usize, max_packets: i32, ptr: *mut c_void) -> color_eyre::Result<u32> {
+/// # Safety
+/// `ptr` must be null or valid for writing `max_packets` packets in the context's layout.
+pub unsafe fn packets_get(
+    ctx_id: usize,
+    max_packets: i32,
+    ptr: *mut c_void,
+) -> color_eyre::Result<u32> {
     let mut state = get_state_or_init().unwrap();
     let state = state.as_mut().unwrap();
     let ctx = state
@@ -569,34 +1438,30 @@ pub fn packets_get(ctx_id: usize, max_packets: i32, ptr: *mut c_void) -> color_e
         ctx.packets.clear();
         return Ok(0);
     }
-    let mut count = 0;
-    for i in 0..max_packets {
-        let packet = match ctx.packets.pop_front() {
-            Some(x) => x,
-            None => break,
-        };
-        // TODO: FIXME: ooooh scary pointer arithmetics.
-        let packet_size = size_of::<Packet>();
-        packet.write(
-            ptr.wrapping_add(packet_size * (i as usize)),
-            ctx.logical_context.packet_data,
-        );
-        count += 1;
-    }
+    let count = ctx.packets.len().min(max_packets.max(0) as usize);
+    let layout = ctx.packet_layout();
+    let packets = ctx.packets.drain(..count).collect::<Vec<_>>();
+    unsafe { layout.write_ptr(&packets, ptr) };
 
     Ok(count as u32)
 }
 
 // only by hope the parameters of this function may be determined
 // bask in the glory of https://developer-docs.wacom.com/docs/icbt/windows/wintab/wintab-reference/#wtpacketspeek
+/// # Safety
+/// `ptr` must be null or valid for writing `max_packets` packets in the context's layout.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTPacketsPeek(ctx_id: usize, max_packets: i32, ptr: *mut c_void) -> u32 {
+pub unsafe extern "C-unwind" fn WTPacketsPeek(
+    ctx_id: usize,
+    max_packets: i32,
+    ptr: *mut c_void,
+) -> u32 {
     // pub extern "C-unwind" fn WTPacketsPeek(ctx_id: usize, ext: u32, ptr: *mut c_void) -> i32 {
     debug!(
         "WTPacketsPeek({:#?}, {:#?}, {:#?})",
         ctx_id, max_packets, ptr
     );
-    match packets_peek(ctx_id, max_packets, ptr) {
+    match unsafe { packets_peek(ctx_id, max_packets, ptr) } {
         Ok(v) => v,
         Err(err) => {
             error!(
@@ -608,7 +1473,13 @@ pub extern "C-unwind" fn WTPacketsPeek(ctx_id: usize, max_packets: i32, ptr: *mu
         }
     }
 }
-pub fn packets_peek(ctx_id: usize, max_packets: i32, ptr: *mut c_void) -> color_eyre::Result<u32> {
+/// # Safety
+/// `ptr` must be null or valid for writing `max_packets` packets in the context's layout.
+pub unsafe fn packets_peek(
+    ctx_id: usize,
+    max_packets: i32,
+    ptr: *mut c_void,
+) -> color_eyre::Result<u32> {
     let mut state = get_state_or_init().unwrap();
     let state = state.as_mut().unwrap();
     let ctx = state
@@ -620,29 +1491,19 @@ pub fn packets_peek(ctx_id: usize, max_packets: i32, ptr: *mut c_void) -> color_
         ctx.packets.clear();
         return Ok(0);
     }
-    let mut count = 0;
-    let mut packets = ctx.packets.iter();
-    for i in 0..max_packets {
-        let packet = match packets.next() {
-            Some(x) => x,
-            None => break,
-        };
-        // TODO: FIXME: ooooh scary pointer arithmetics.
-        let packet_size = size_of::<Packet>();
-        packet.write(
-            ptr.wrapping_add(packet_size * (i as usize)),
-            ctx.logical_context.packet_data,
-        );
-        count += 1;
-    }
+    let count = ctx.packets.len().min(max_packets.max(0) as usize);
+    let layout = ctx.packet_layout();
+    unsafe { layout.write_ptr(ctx.packets.iter().take(count), ptr) };
 
     Ok(count as u32)
 }
 
+/// # Safety
+/// `ptr` must be null or valid for writing one packet in the context's layout.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTPacket(ctx_id: usize, serial: u32, ptr: *mut c_void) -> bool {
+pub unsafe extern "C-unwind" fn WTPacket(ctx_id: usize, serial: u32, ptr: *mut c_void) -> bool {
     debug!("WTPacket({:#?}, {:#?}, {:#?})", ctx_id, serial, ptr);
-    match packet(ctx_id, serial, ptr) {
+    match unsafe { packet(ctx_id, serial, ptr) } {
         Ok(v) => v,
         Err(err) => {
             error!("WTPacket({:#?}, {:#?}, {:#?}) failed!", ctx_id, serial, ptr);
@@ -651,102 +1512,350 @@ pub extern "C-unwind" fn WTPacket(ctx_id: usize, serial: u32, ptr: *mut c_void)
         }
     }
 }
-pub fn packet(ctx_id: usize, serial: u32, ptr: *mut c_void) -> color_eyre::Result<bool> {
+/// # Safety
+/// `ptr` must be null or valid for writing one packet in the context's layout.
+pub unsafe fn packet(ctx_id: usize, serial: u32, ptr: *mut c_void) -> color_eyre::Result<bool> {
     let mut state = get_state_or_init().unwrap();
     let state = state.as_mut().unwrap();
     let ctx = state
         .contexts
         .get_mut(&ctx_id)
         .wrap_err("context not found")?;
-    ctx.packets.retain_mut(|x| x.serial >= serial);
-    let packet = match ctx.packets.iter().find(|x| x.serial == serial) {
-        Some(x) => x,
-        None => return Ok(false),
-    };
-    packet.write(ptr, ctx.logical_context.packet_data);
-    ctx.packets.retain_mut(|x| x.serial > serial);
-    Ok(true)
-}
-
-#[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTOverlap(ctx_id: usize, overlap: bool) -> bool {
-    debug!("!STUB! WTOverlap({:#?}, {:#?}) -> true", ctx_id, overlap);
-    true
+    Ok(unsafe { ctx.take_packet(serial, ptr) })
 }
 
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTClose(ctx_id: usize) -> bool {
-    debug!("WTClose({:#?})", ctx_id);
-    match close(ctx_id) {
+pub extern "C-unwind" fn WTOverlap(ctx_id: usize, to_top: bool) -> bool {
+    debug!("WTOverlap({:#?}, {:#?})", ctx_id, to_top);
+    match overlap(ctx_id, to_top) {
         Ok(v) => v,
         Err(err) => {
-            error!("WTClose({:#?}) failed!", ctx_id);
+            error!("WTOverlap({:#?}, {:#?}) failed!", ctx_id, to_top);
             error!("{:?}", err);
             false
         }
     }
 }
-pub fn close(ctx_id: usize) -> color_eyre::Result<bool> {
+pub fn overlap(ctx_id: usize, to_top: bool) -> color_eyre::Result<bool> {
     let mut state = get_state_or_init().unwrap();
     let state = state.as_mut().unwrap();
-    state.contexts.retain(|i, _| *i != ctx_id);
+    if !state.contexts.contains_key(&ctx_id) {
+        bail!("context not found");
+    }
+    if state.default_context_key(ctx_id).is_some() {
+        bail!("default contexts aren't in the overlap order");
+    }
+    if to_top {
+        state.raise(ctx_id);
+    } else {
+        state.lower(ctx_id);
+    }
+    Ok(true)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTClose(ctx_id: usize) -> bool {
+    debug!("WTClose({:#?})", ctx_id);
+    match close(ctx_id) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTClose({:#?}) failed!", ctx_id);
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+pub fn close(ctx_id: usize) -> color_eyre::Result<bool> {
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    state
+        .close_context(ctx_id, true)
+        .wrap_err("context not found")?;
     Ok(true)
 }
 
+/// # Safety
+/// `ptr` must be valid for writing a LOGCONTEXTA.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTGetA(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTGetA({:#?}, {:#?})", ctx_id, ptr);
-    WTGet(ctx_id, ptr)
+pub unsafe extern "C-unwind" fn WTGetA(ctx_id: usize, ptr: *mut LogContextA) -> bool {
+    debug!("WTGetA({:#?}, {:#?})", ctx_id, ptr);
+    unsafe { get(ctx_id, ptr as *mut c_void, Charset::Ansi) }
 }
+/// # Safety
+/// `ptr` must be valid for writing a LOGCONTEXTW.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTGetW(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTGetW({:#?}, {:#?})", ctx_id, ptr);
-    WTGet(ctx_id, ptr)
+pub unsafe extern "C-unwind" fn WTGetW(ctx_id: usize, ptr: *mut LogContextW) -> bool {
+    debug!("WTGetW({:#?}, {:#?})", ctx_id, ptr);
+    unsafe { get(ctx_id, ptr as *mut c_void, Charset::Wide) }
 }
+/// # Safety
+/// `ptr` must be valid for writing a LOGCONTEXTW.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTGet(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTGet({:#?}, {:#?})", ctx_id, ptr);
-    false
+pub unsafe extern "C-unwind" fn WTGet(ctx_id: usize, ptr: *mut LogContextW) -> bool {
+    debug!("WTGet({:#?}, {:#?})", ctx_id, ptr);
+    unsafe { get(ctx_id, ptr as *mut c_void, Charset::Wide) }
+}
+/// # Safety
+/// `ptr` must be null or valid for writing a LOGCONTEXTA or LOGCONTEXTW, depending on `charset`.
+pub unsafe fn get(ctx_id: usize, ptr: *mut c_void, charset: Charset) -> bool {
+    if ptr.is_null() {
+        error!("WTGet ptr is null");
+        return false;
+    }
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    let Some(ctx) = state.contexts.get(&ctx_id) else {
+        error!("WTGet({:#?}, {:#?}) failed! context not found", ctx_id, ptr);
+        return false;
+    };
+    unsafe { ctx.logical_context.write_ptr(ptr, charset) };
+    true
 }
 
+/// # Safety
+/// `ptr` must point to a valid LOGCONTEXTA.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTSetA(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTSetA({:#?}, {:#?})", ctx_id, ptr);
-    WTGet(ctx_id, ptr)
+pub unsafe extern "C-unwind" fn WTSetA(ctx_id: usize, ptr: *mut LogContextA) -> bool {
+    debug!("WTSetA({:#?}, {:#?})", ctx_id, ptr);
+    unsafe { set(ctx_id, ptr as *const c_void, Charset::Ansi) }
 }
+/// # Safety
+/// `ptr` must point to a valid LOGCONTEXTW.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTSetW(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTSetW({:#?}, {:#?})", ctx_id, ptr);
-    WTGet(ctx_id, ptr)
+pub unsafe extern "C-unwind" fn WTSetW(ctx_id: usize, ptr: *mut LogContextW) -> bool {
+    debug!("WTSetW({:#?}, {:#?})", ctx_id, ptr);
+    unsafe { set(ctx_id, ptr as *const c_void, Charset::Wide) }
 }
+/// # Safety
+/// `ptr` must point to a valid LOGCONTEXTW.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTSet(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTSet({:#?}, {:#?})", ctx_id, ptr);
-    false
+pub unsafe extern "C-unwind" fn WTSet(ctx_id: usize, ptr: *mut LogContextW) -> bool {
+    debug!("WTSet({:#?}, {:#?})", ctx_id, ptr);
+    unsafe { set(ctx_id, ptr as *const c_void, Charset::Wide) }
+}
+/// # Safety
+/// `ptr` must be null or point to a valid LOGCONTEXTA or LOGCONTEXTW, depending on `charset`.
+pub unsafe fn set(ctx_id: usize, ptr: *const c_void, charset: Charset) -> bool {
+    if ptr.is_null() {
+        error!("WTSet ptr is null");
+        return false;
+    }
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    let default_key = state.default_context_key(ctx_id);
+    let logical_context = unsafe { WtiLogicalContext::read_ptr(ptr, charset) };
+    if logical_context.device as usize >= state.devices.len() {
+        error!(
+            "WTSet({:#?}, {:#?}) failed! unknown device {}",
+            ctx_id, ptr, logical_context.device
+        );
+        return false;
+    }
+    let Some(ctx) = state.contexts.get_mut(&ctx_id) else {
+        error!("WTSet({:#?}, {:#?}) failed! context not found", ctx_id, ptr);
+        return false;
+    };
+    // status is owned by PSM, the rest comes from the caller
+    let status = ctx.logical_context.status;
+    ctx.logical_context = logical_context;
+    ctx.logical_context.status = status;
+    if let Some((device, system)) = default_key {
+        // default contexts are what WTInfo reports for new contexts,
+        // each handle only changes the defaults of its own device and category
+        let mut lc = ctx.logical_context.clone();
+        lc.device = device;
+        lc.options = (lc.options & !CXO_SYSTEM) | if system { CXO_SYSTEM } else { 0 };
+        lc.status = 0;
+        ctx.logical_context.device = device;
+        state.default_contexts.insert((device, system), lc);
+    } else if let Err(err) = ctx.context_update() {
+        error!("Couldn't send the context update! {:?}", err);
+    }
+    state.notify_managers(
+        WindowMessage::CtxUpdate,
+        WPARAM(ctx_id),
+        LPARAM(status as isize),
+    );
+    true
 }
 
+/// # Safety
+/// `ptr` must point to an EXTPROPERTY followed by `dataSize` bytes of data.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTExtGet(ctx_id: usize, ext: u32, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTExtGet({:#?}, {:#?}, {:#?})", ctx_id, ext, ptr);
-    false
+pub unsafe extern "C-unwind" fn WTExtGet(ctx_id: usize, ext: u32, ptr: *mut c_void) -> bool {
+    debug!("WTExtGet({:#?}, {:#?}, {:#?})", ctx_id, ext, ptr);
+    if ptr.is_null() {
+        error!("WTExtGet ptr is null");
+        return false;
+    }
+    let property = unsafe { &*(ptr as *const ExtProperty) };
+    debug!("ExtProperty -> {:#?}", property);
+    match ext_get(ctx_id, ext, property) {
+        Ok(Some(value)) => {
+            let size = (property.data_size as usize).min(size_of::<u32>());
+            unsafe {
+                let data = ptr.wrapping_add(size_of::<ExtProperty>()) as *mut u8;
+                std::ptr::copy(value.to_le_bytes().as_ptr(), data, size);
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(err) => {
+            error!("WTExtGet({:#?}, {:#?}, {:#?}) failed!", ctx_id, ext, ptr);
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+pub fn ext_get(ctx_id: usize, ext: u32, property: &ExtProperty) -> color_eyre::Result<Option<u32>> {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    let ctx = state.contexts.get(&ctx_id).wrap_err("context not found")?;
+    let extension = state
+        .extensions
+        .iter()
+        .find(|x| x.tag == ext)
+        .wrap_err("extension not found")?;
+    if property.property_id == TABLET_PROPERTY_OVERRIDE {
+        let key = (ext, property.control_index, property.function_index);
+        return Ok(Some(ctx.ext_overrides.get(&key).copied().unwrap_or(0)));
+    }
+    Ok(extension.property(
+        property.control_index,
+        property.function_index,
+        property.property_id,
+    ))
 }
 
+/// # Safety
+/// `ptr` must point to an EXTPROPERTY followed by `dataSize` bytes of data.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTExtSet(ctx_id: usize, ext: u32, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTExtSet({:#?}, {:#?}, {:#?})", ctx_id, ext, ptr);
-    false
+pub unsafe extern "C-unwind" fn WTExtSet(ctx_id: usize, ext: u32, ptr: *mut c_void) -> bool {
+    debug!("WTExtSet({:#?}, {:#?}, {:#?})", ctx_id, ext, ptr);
+    if ptr.is_null() {
+        error!("WTExtSet ptr is null");
+        return false;
+    }
+    let property = unsafe { &*(ptr as *const ExtProperty) };
+    debug!("ExtProperty -> {:#?}", property);
+    let mut value = [0u8; 4];
+    let size = (property.data_size as usize).min(value.len());
+    unsafe {
+        let data = ptr.wrapping_add(size_of::<ExtProperty>()) as *const u8;
+        std::ptr::copy(data, value.as_mut_ptr(), size);
+    }
+    match ext_set(ctx_id, ext, property, u32::from_le_bytes(value)) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTExtSet({:#?}, {:#?}, {:#?}) failed!", ctx_id, ext, ptr);
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+pub fn ext_set(
+    ctx_id: usize,
+    ext: u32,
+    property: &ExtProperty,
+    value: u32,
+) -> color_eyre::Result<bool> {
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    let extension = state
+        .extensions
+        .iter()
+        .find(|x| x.tag == ext)
+        .wrap_err("extension not found")?;
+    if property.control_index as u32 >= extension.controls {
+        bail!("control {} not found", property.control_index);
+    }
+    let ctx = state
+        .contexts
+        .get_mut(&ctx_id)
+        .wrap_err("context not found")?;
+    match property.property_id {
+        TABLET_PROPERTY_OVERRIDE => {
+            let key = (ext, property.control_index, property.function_index);
+            ctx.ext_overrides.insert(key, value);
+            Ok(true)
+        }
+        // PSM doesn't display anything, so there's nothing to use the name for
+        TABLET_PROPERTY_OVERRIDE_NAME => Ok(true),
+        _ => Ok(false),
+    }
 }
 
+/// # Safety
+/// `ptr` must be valid for writing [SAVE_SIZE](save::SAVE_SIZE) bytes.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTSave(ctx_id: usize, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTSave({:#?}, {:#?})", ctx_id, ptr);
-    false
+pub unsafe extern "C-unwind" fn WTSave(ctx_id: usize, ptr: *mut c_void) -> bool {
+    debug!("WTSave({:#?}, {:#?})", ctx_id, ptr);
+    if ptr.is_null() {
+        error!("WTSave ptr is null");
+        return false;
+    }
+    match save(ctx_id) {
+        Ok(data) => {
+            unsafe {
+                std::ptr::copy(data.as_ptr(), ptr as *mut u8, data.len());
+            }
+            true
+        }
+        Err(err) => {
+            error!("WTSave({:#?}, {:#?}) failed!", ctx_id, ptr);
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+pub fn save(ctx_id: usize) -> color_eyre::Result<Vec<u8>> {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    let ctx = state.contexts.get(&ctx_id).wrap_err("context not found")?;
+    Ok(SavedContext {
+        logical_context: ctx.logical_context.clone(),
+        queue_size: ctx.queue_size as u32,
+    }
+    .to_bytes())
 }
 
+/// # Safety
+/// `ptr` must point to save information written by [WTSave].
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTRestore(hwnd: HWND, ptr: *mut c_void, value: bool) -> usize {
-    debug!("!STUB! WTRestore({:#?}, {:#?}, {:#?})", hwnd, ptr, value);
-    0
+pub unsafe extern "C-unwind" fn WTRestore(hwnd: HWND, ptr: *mut c_void, enable: bool) -> usize {
+    debug!("WTRestore({:#?}, {:#?}, {:#?})", hwnd, ptr, enable);
+    if ptr.is_null() {
+        error!("WTRestore ptr is null");
+        return 0;
+    }
+    // the header is checked before reading the rest, so foreign blobs shorter than ours are safe
+    let header = unsafe { std::slice::from_raw_parts(ptr as *const u8, SAVE_HEADER_SIZE) };
+    let size = match save::blob_size(header) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTRestore({:#?}, {:#?}, {:#?}) failed!", hwnd, ptr, enable);
+            error!("{:?}", err);
+            return 0;
+        }
+    };
+    let data = unsafe { std::slice::from_raw_parts(ptr as *const u8, size) };
+    match restore(hwnd, data, enable) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTRestore({:#?}, {:#?}, {:#?}) failed!", hwnd, ptr, enable);
+            error!("{:?}", err);
+            0
+        }
+    }
+}
+pub fn restore(hwnd: HWND, data: &[u8], enable: bool) -> color_eyre::Result<usize> {
+    let saved = SavedContext::from_bytes(data)?;
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    let mut context = Context::new(0, enable);
+    context.logical_context = saved.logical_context;
+    context.queue_size = saved.queue_size as usize;
+    Ok(state.open_context(hwnd, context))
 }
 
 #[unsafe(no_mangle)]
@@ -781,17 +1890,51 @@ pub extern "C-unwind" fn WTDataPeek(
     0
 }
 
+/// # Safety
+/// `old` and `new` must be valid for writing a `UINT` each.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTQueuePacketsEx(
+pub unsafe extern "C-unwind" fn WTQueuePacketsEx(
     ctx_id: usize,
-    old: *mut c_void,
-    new: *mut c_void,
+    old: *mut u32,
+    new: *mut u32,
 ) -> bool {
-    debug!(
-        "!STUB! WTQueuePacketsEx({:#?}, {:#?}, {:#?})",
-        ctx_id, old, new
-    );
-    false
+    debug!("WTQueuePacketsEx({:#?}, {:#?}, {:#?})", ctx_id, old, new);
+    match queue_packets_ex(ctx_id) {
+        Ok(Some((oldest, newest))) => {
+            if old.is_null() || new.is_null() {
+                error!("WTQueuePacketsEx output pointer is null");
+                return false;
+            }
+            unsafe {
+                *old = oldest;
+                *new = newest;
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(err) => {
+            error!(
+                "WTQueuePacketsEx({:#?}, {:#?}, {:#?}) failed!",
+                ctx_id, old, new
+            );
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+/// Returns serials of the oldest and newest queued packets, or [None] if the queue is empty.
+pub fn queue_packets_ex(ctx_id: usize) -> color_eyre::Result<Option<(u32, u32)>> {
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    let ctx = state
+        .contexts
+        .get_mut(&ctx_id)
+        .wrap_err("context not found")?;
+    Ok(ctx
+        .packets
+        .front()
+        .zip(ctx.packets.back())
+        .map(|(oldest, newest)| (oldest.serial, newest.serial)))
 }
 
 #[unsafe(no_mangle)]
@@ -835,51 +1978,612 @@ pub fn queue_size_set(ctx_id: usize, num_packets: u32) -> color_eyre::Result<boo
         .contexts
         .get_mut(&ctx_id)
         .wrap_err("context not found")?;
-    ctx.queue_size = num_packets as usize;
+    // the old queue is always deleted, even if the new size is rejected
+    ctx.packets.clear();
+    ctx.overflowed = false;
+    let num_packets = num_packets as usize;
+    if num_packets == 0 || num_packets > MAX_QUEUE_SIZE {
+        ctx.queue_size = 0;
+        warn!(
+            "Rejecting queue size {} for context {}, the context has no queue now",
+            num_packets, ctx_id
+        );
+        return Ok(false);
+    }
+    ctx.queue_size = num_packets;
     Ok(true)
 }
 
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTMgrOpen(hwnd: HWND, msg_base: u32) -> usize {
-    debug!("!STUB! WTMgrOpen({:#?}, {:#?})", hwnd, msg_base);
-    0
+pub extern "C-unwind" fn WTConfig(ctx_id: usize, hwnd: HWND) -> bool {
+    debug!("WTConfig({:#?}, {:#?})", ctx_id, hwnd);
+    // PSM has no configuration dialog, the context is never changed
+    false
 }
 
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTMgrExt(mgr: usize, value: u32, ptr: *mut c_void) -> bool {
-    debug!("!STUB! WTMgrExt({:#?}, {:#?}, {:#?})", mgr, value, ptr);
-    false
+pub extern "C-unwind" fn WTMgrOpen(hwnd: HWND, msg_base: u32) -> usize {
+    debug!("WTMgrOpen({:#?}, {:#?})", hwnd, msg_base);
+    match mgr_open(hwnd, msg_base) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTMgrOpen({:#?}, {:#?}) failed!", hwnd, msg_base);
+            error!("{:?}", err);
+            0
+        }
+    }
+}
+pub fn mgr_open(hwnd: HWND, msg_base: u32) -> color_eyre::Result<usize> {
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    if hwnd.0.is_null() {
+        bail!("manager window is null");
+    }
+    if state.managers.len() >= MAX_MANAGERS as usize {
+        bail!("too many managers are open");
+    }
+    state.counter += 1;
+    let handle = state.counter;
+    state.managers.insert(
+        handle,
+        Manager {
+            handle,
+            window: ThreadHWND(hwnd),
+            msg_base,
+        },
+    );
+    debug!("new manager registered at {}", handle);
+    Ok(handle)
 }
 
 #[unsafe(no_mangle)]
 pub extern "C-unwind" fn WTMgrClose(mgr: usize) -> bool {
-    debug!("!STUB! WTMgrClose({:#?})", mgr);
+    debug!("WTMgrClose({:#?})", mgr);
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    if state.managers.remove(&mgr).is_none() {
+        return false;
+    }
+    hooks::uninstall_manager(mgr);
+    true
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrExt(mgr: usize, ext: u32, ptr: *mut c_void) -> bool {
+    debug!("WTMgrExt({:#?}, {:#?}, {:#?})", mgr, ext, ptr);
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    if !state.managers.contains_key(&mgr) {
+        error!(
+            "WTMgrExt({:#?}, {:#?}, {:#?}) failed! manager not found",
+            mgr, ext, ptr
+        );
+        return false;
+    }
+    // none of the supported extensions have global data
     false
 }
 
+pub type WTENUMPROC = unsafe extern "system" fn(ctx: usize, lparam: LPARAM) -> windows::core::BOOL;
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrContextEnum(
+    mgr: usize,
+    enum_func: Option<WTENUMPROC>,
+    lparam: LPARAM,
+) -> bool {
+    debug!(
+        "WTMgrContextEnum({:#?}, {:#?}, {:#?})",
+        mgr,
+        enum_func.is_some(),
+        lparam
+    );
+    let Some(enum_func) = enum_func else {
+        error!("WTMgrContextEnum enum_func is null");
+        return false;
+    };
+    // the callback may call back into PSM, so the state can't stay locked
+    let handles = {
+        let state = get_state_or_init().unwrap();
+        let state = state.as_ref().unwrap();
+        if !state.managers.contains_key(&mgr) {
+            error!("WTMgrContextEnum({:#?}) failed! manager not found", mgr);
+            return false;
+        }
+        state.order.clone()
+    };
+    for handle in handles {
+        if !unsafe { enum_func(handle, lparam) }.as_bool() {
+            break;
+        }
+    }
+    true
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrContextOwner(mgr: usize, ctx_id: usize) -> HWND {
+    debug!("WTMgrContextOwner({:#?}, {:#?})", mgr, ctx_id);
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    if !state.managers.contains_key(&mgr) {
+        error!("WTMgrContextOwner({:#?}) failed! manager not found", mgr);
+        return HWND::default();
+    }
+    state
+        .contexts
+        .get(&ctx_id)
+        .map(|ctx| ctx.window.0)
+        .unwrap_or_default()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrDefContext(mgr: usize, system: bool) -> usize {
+    debug!("WTMgrDefContext({:#?}, {:#?})", mgr, system);
+    WTMgrDefContextEx(mgr, 0, system)
+}
+
 #[unsafe(no_mangle)]
 pub extern "C-unwind" fn WTMgrDefContextEx(mgr: usize, device: u32, system: bool) -> usize {
     debug!(
-        "!STUB! WTMgrDefContextEx({:#?}, {:#?}, {:#?})",
+        "WTMgrDefContextEx({:#?}, {:#?}, {:#?})",
         mgr, device, system
     );
-    0
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    if !state.managers.contains_key(&mgr) {
+        error!("WTMgrDefContextEx({:#?}) failed! manager not found", mgr);
+        return 0;
+    }
+    state.default_context_handle(device, system).unwrap_or(0)
 }
 
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn WTMgrPacketHookDefProc(
-    value1: i32,
+pub extern "C-unwind" fn WTMgrDeviceConfig(mgr: usize, device: u32, hwnd: HWND) -> u32 {
+    debug!("WTMgrDeviceConfig({:#?}, {:#?}, {:#?})", mgr, device, hwnd);
+    // PSM is configured through psm.json and the client
+    WTDC_NONE
+}
+
+/// # Safety
+/// `module` and `proc_name` must be null or null-terminated ANSI strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrConfigReplaceExA(
+    mgr: usize,
+    install: bool,
+    module: PCSTR,
+    proc_name: PCSTR,
+) -> bool {
+    debug!(
+        "WTMgrConfigReplaceExA({:#?}, {:#?}, {:?}, {:?})",
+        mgr,
+        install,
+        unsafe { module.to_string() },
+        unsafe { proc_name.to_string() }
+    );
+    mgr_config_replace_ex(mgr, install)
+}
+/// # Safety
+/// `module` must be null or a null-terminated UTF-16 string, `proc_name` a null-terminated ANSI one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrConfigReplaceExW(
+    mgr: usize,
+    install: bool,
+    module: PCWSTR,
+    proc_name: PCSTR,
+) -> bool {
+    debug!(
+        "WTMgrConfigReplaceExW({:#?}, {:#?}, {:?}, {:?})",
+        mgr,
+        install,
+        unsafe { module.to_string() },
+        unsafe { proc_name.to_string() }
+    );
+    mgr_config_replace_ex(mgr, install)
+}
+/// Wintab 1.0 version of [WTMgrConfigReplaceExA], taking the procedure itself.
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrConfigReplaceA(
+    mgr: usize,
+    install: bool,
+    config_proc: *const c_void,
+) -> bool {
+    debug!(
+        "WTMgrConfigReplaceA({:#?}, {:#?}, {:#?})",
+        mgr, install, config_proc
+    );
+    mgr_config_replace_ex(mgr, install)
+}
+/// Wintab 1.0 version of [WTMgrConfigReplaceExW], taking the procedure itself.
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrConfigReplaceW(
+    mgr: usize,
+    install: bool,
+    config_proc: *const c_void,
+) -> bool {
+    debug!(
+        "WTMgrConfigReplaceW({:#?}, {:#?}, {:#?})",
+        mgr, install, config_proc
+    );
+    mgr_config_replace_ex(mgr, install)
+}
+/// PSM has no configuration dialog for WTConfig to show, so there is nothing to replace.
+/// Removing a replacement always works, installing one is refused.
+pub fn mgr_config_replace_ex(mgr: usize, install: bool) -> bool {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    if !state.managers.contains_key(&mgr) {
+        error!("WTMgrConfigReplaceEx({:#?}) failed! manager not found", mgr);
+        return false;
+    }
+    !install
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrCsrEnable(mgr: usize, cursor: u32, enable: bool) -> bool {
+    debug!("WTMgrCsrEnable({:#?}, {:#?}, {:#?})", mgr, cursor, enable);
+    match mgr_cursor(mgr, cursor, |csr| csr.active = enable as u32) {
+        Ok(_) => true,
+        Err(err) => {
+            error!("WTMgrCsrEnable({:#?}, {:#?}) failed!", mgr, cursor);
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+
+/// # Safety
+/// `log_btns` and `sys_btns` must be null or point to 32 bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrCsrButtonMap(
+    mgr: usize,
+    cursor: u32,
+    log_btns: *const [u8; 32],
+    sys_btns: *const [u8; 32],
+) -> bool {
+    debug!(
+        "WTMgrCsrButtonMap({:#?}, {:#?}, {:#?}, {:#?})",
+        mgr, cursor, log_btns, sys_btns
+    );
+    // a null map is left unchanged
+    let (log_btns, sys_btns) = unsafe { (log_btns.as_ref(), sys_btns.as_ref()) };
+    if log_btns.is_some_and(|x| x.iter().any(|x| *x >= 32)) {
+        error!("WTMgrCsrButtonMap({:#?}, {:#?}) failed!", mgr, cursor);
+        error!("logical buttons must be 0 to 31");
+        return false;
+    }
+    let result = mgr_cursor(mgr, cursor, |csr| {
+        if let Some(map) = log_btns {
+            csr.button_map = *map;
+        }
+        if let Some(map) = sys_btns {
+            csr.system_button_map = *map;
+        }
+    });
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            error!("WTMgrCsrButtonMap({:#?}, {:#?}) failed!", mgr, cursor);
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+
+/// Button marks are packed like MAKELONG(release, press).
+/// Tangential pressure doesn't drive a button, so only normal pressure marks are kept.
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrCsrPressureBtnMarks(
+    mgr: usize,
+    cursor: u32,
+    n_marks: u32,
+    t_marks: u32,
+) -> bool {
+    debug!(
+        "WTMgrCsrPressureBtnMarks({:#?}, {:#?}, {:#x}, {:#x})",
+        mgr, cursor, n_marks, t_marks
+    );
+    let marks = [n_marks & 0xFFFF, n_marks >> 16];
+    unsafe { WTMgrCsrPressureBtnMarksEx(mgr, cursor, &marks, std::ptr::null()) }
+}
+
+/// # Safety
+/// `n_marks` and `t_marks` must be null or point to two UINTs.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrCsrPressureBtnMarksEx(
+    mgr: usize,
+    cursor: u32,
+    n_marks: *const [u32; 2],
+    t_marks: *const [u32; 2],
+) -> bool {
+    debug!(
+        "WTMgrCsrPressureBtnMarksEx({:#?}, {:#?}, {:#?}, {:#?})",
+        mgr, cursor, n_marks, t_marks
+    );
+    let n_marks = unsafe { n_marks.as_ref() };
+    let result = mgr_cursor(mgr, cursor, |csr| {
+        if let Some(marks) = n_marks {
+            csr.set_pressure_button_marks(*marks);
+        }
+    });
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            error!(
+                "WTMgrCsrPressureBtnMarksEx({:#?}, {:#?}) failed!",
+                mgr, cursor
+            );
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+
+/// # Safety
+/// `np_response` and `tp_response` must be null or point to as many UINTs as
+/// CSR_NPRESPONSE and CSR_TPRESPONSE return.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrCsrPressureResponse(
+    mgr: usize,
+    cursor: u32,
+    np_response: *const [u32; RESPONSE_SAMPLES],
+    tp_response: *const [u32; RESPONSE_SAMPLES],
+) -> bool {
+    debug!(
+        "WTMgrCsrPressureResponse({:#?}, {:#?}, {:#?}, {:#?})",
+        mgr, cursor, np_response, tp_response
+    );
+    // tangential pressure is always reported as is
+    let np_response = unsafe { np_response.as_ref() };
+    let result = mgr_cursor(mgr, cursor, |csr| {
+        if let Some(response) = np_response {
+            csr.set_pressure_response(response);
+        }
+    });
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            error!(
+                "WTMgrCsrPressureResponse({:#?}, {:#?}) failed!",
+                mgr, cursor
+            );
+            error!("{:?}", err);
+            false
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrCsrExt(mgr: usize, cursor: u32, ext: u32, ptr: *mut c_void) -> bool {
+    debug!(
+        "WTMgrCsrExt({:#?}, {:#?}, {:#?}, {:#?})",
+        mgr, cursor, ext, ptr
+    );
+    // none of the supported extensions have per-cursor data
+    false
+}
+
+/// Changes a cursor type on behalf of a manager, posting WT_INFOCHANGE for what changed.
+pub fn mgr_cursor(
+    mgr: usize,
+    cursor: u32,
+    change: impl FnOnce(&mut WtiCursor),
+) -> color_eyre::Result<()> {
+    let mut state = get_state_or_init().unwrap();
+    let state = state.as_mut().unwrap();
+    if !state.managers.contains_key(&mgr) {
+        bail!("manager not found");
+    }
+    let before = state.info_items();
+    let Some(csr) = state.cursors.get_mut(cursor as usize) else {
+        bail!("cursor {} not found", cursor);
+    };
+    change(csr);
+    state.notify_info_changes(&before);
+    Ok(())
+}
+
+/// # Safety
+/// `module` and `proc_name` must be null-terminated ANSI strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrPacketHookExA(
+    mgr: usize,
+    kind: i32,
+    module: PCSTR,
+    proc_name: PCSTR,
+) -> usize {
+    debug!(
+        "WTMgrPacketHookExA({:#?}, {:#?}, {:?}, {:?})",
+        mgr,
+        kind,
+        unsafe { module.to_string() },
+        unsafe { proc_name.to_string() }
+    );
+    let module = (!module.is_null()).then(|| Charset::Ansi.decode(unsafe { module.as_bytes() }));
+    match mgr_packet_hook_ex(mgr, kind, module, proc_name) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTMgrPacketHookExA({:#?}, {:#?}) failed!", mgr, kind);
+            error!("{:?}", err);
+            0
+        }
+    }
+}
+/// # Safety
+/// `module` must be a null-terminated UTF-16 string, `proc_name` a null-terminated ANSI one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrPacketHookExW(
+    mgr: usize,
+    kind: i32,
+    module: PCWSTR,
+    proc_name: PCSTR,
+) -> usize {
+    debug!(
+        "WTMgrPacketHookExW({:#?}, {:#?}, {:?}, {:?})",
+        mgr,
+        kind,
+        unsafe { module.to_string() },
+        unsafe { proc_name.to_string() }
+    );
+    let module = (!module.is_null()).then(|| unsafe { module.to_string() }.unwrap_or_default());
+    match mgr_packet_hook_ex(mgr, kind, module, proc_name) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTMgrPacketHookExW({:#?}, {:#?}) failed!", mgr, kind);
+            error!("{:?}", err);
+            0
+        }
+    }
+}
+/// # Safety
+/// `module` and `proc_name` must be null-terminated ANSI strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrPacketHookEx(
+    mgr: usize,
+    kind: i32,
+    module: PCSTR,
+    proc_name: PCSTR,
+) -> usize {
+    unsafe { WTMgrPacketHookExA(mgr, kind, module, proc_name) }
+}
+pub fn mgr_packet_hook_ex(
+    mgr: usize,
+    kind: i32,
+    module: Option<String>,
+    proc_name: PCSTR,
+) -> color_eyre::Result<usize> {
+    {
+        let state = get_state_or_init().unwrap();
+        let state = state.as_ref().unwrap();
+        if !state.managers.contains_key(&mgr) {
+            bail!("manager not found");
+        }
+    }
+    let Some(module) = module.filter(|_| !proc_name.is_null()) else {
+        bail!("module or hook procedure name is null");
+    };
+    hooks::install(mgr, kind, &module, proc_name)
+}
+
+/// Wintab 1.0 version of [WTMgrPacketHookExA], taking the procedure itself.
+/// Returns the procedure the hook should pass packets on to with [WTMgrPacketHookDefProc].
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrPacketHookA(
+    mgr: usize,
+    install: bool,
+    kind: i32,
+    proc_: Option<hooks::WTHOOKPROC>,
+) -> Option<hooks::WTHOOKPROC> {
+    debug!(
+        "WTMgrPacketHookA({:#?}, {:#?}, {:#?}, {:#?})",
+        mgr,
+        install,
+        kind,
+        proc_.map(|x| x as *const c_void)
+    );
+    match mgr_packet_hook(mgr, install, kind, proc_) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTMgrPacketHookA({:#?}, {:#?}) failed!", mgr, kind);
+            error!("{:?}", err);
+            None
+        }
+    }
+}
+/// Wintab 1.0 version of [WTMgrPacketHookExW], taking the procedure itself.
+/// Returns the procedure the hook should pass packets on to with [WTMgrPacketHookDefProc].
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrPacketHookW(
+    mgr: usize,
+    install: bool,
+    kind: i32,
+    proc_: Option<hooks::WTHOOKPROC>,
+) -> Option<hooks::WTHOOKPROC> {
+    debug!(
+        "WTMgrPacketHookW({:#?}, {:#?}, {:#?}, {:#?})",
+        mgr,
+        install,
+        kind,
+        proc_.map(|x| x as *const c_void)
+    );
+    match mgr_packet_hook(mgr, install, kind, proc_) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("WTMgrPacketHookW({:#?}, {:#?}) failed!", mgr, kind);
+            error!("{:?}", err);
+            None
+        }
+    }
+}
+pub fn mgr_packet_hook(
+    mgr: usize,
+    install: bool,
+    kind: i32,
+    proc_: Option<hooks::WTHOOKPROC>,
+) -> color_eyre::Result<Option<hooks::WTHOOKPROC>> {
+    {
+        let state = get_state_or_init().unwrap();
+        let state = state.as_ref().unwrap();
+        if !state.managers.contains_key(&mgr) {
+            bail!("manager not found");
+        }
+    }
+    let proc_ = proc_.wrap_err("hook procedure is null")?;
+    if !install {
+        if !hooks::uninstall_proc(mgr, kind, proc_) {
+            bail!("hook procedure isn't installed");
+        }
+        return Ok(None);
+    }
+    let next = hooks::first(kind).map(|x| x.proc_);
+    hooks::install_proc(mgr, kind, proc_)?;
+    Ok(next)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrPacketUnhook(hook: usize) -> bool {
+    debug!("WTMgrPacketUnhook({:#?})", hook);
+    hooks::uninstall(hook)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C-unwind" fn WTMgrPacketHookNext(
+    hook: usize,
+    code: i32,
     w: WPARAM,
     l: LPARAM,
-    hook: *mut c_void,
-) -> *mut c_void {
+) -> LRESULT {
     debug!(
-        "!STUB! WTMgrPacketHookDefProc({:#?}, {:#?}, {:#?}, {:#?})",
-        value1, w, l, hook
+        "WTMgrPacketHookNext({:#?}, {:#?}, {:#?}, {:#?})",
+        hook, code, w, l
     );
-    std::ptr::null_mut()
+    match hooks::next(hook) {
+        Some(next) => hooks::call(next, code, w, l),
+        None => LRESULT(0),
+    }
 }
 
+/// # Safety
+/// `hook` must be null or point to a hook procedure pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn WTMgrPacketHookDefProc(
+    code: i32,
+    w: WPARAM,
+    l: LPARAM,
+    hook: *mut Option<hooks::WTHOOKPROC>,
+) -> LRESULT {
+    debug!(
+        "WTMgrPacketHookDefProc({:#?}, {:#?}, {:#?}, {:#?})",
+        code, w, l, hook
+    );
+    // Wintab 1.0 hook chains store the next procedure themselves
+    match unsafe { hook.as_ref() }.copied().flatten() {
+        Some(next) => unsafe { next(code, w, l) },
+        None => LRESULT(0),
+    }
+}
+
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
 #[unsafe(no_mangle)]
 pub unsafe extern "C-unwind" fn WTInfoA(
     w_category: u32,
@@ -887,8 +2591,10 @@ pub unsafe extern "C-unwind" fn WTInfoA(
     lp_output: *mut c_void,
 ) -> u32 {
     debug!("WTInfoA({}, {}, {:#?});", w_category, n_index, lp_output);
-    unsafe { WTInfo(w_category, n_index, lp_output) }
+    unsafe { info(w_category, n_index, Charset::Ansi, lp_output) }
 }
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
 #[unsafe(no_mangle)]
 pub unsafe extern "C-unwind" fn WTInfoW(
     w_category: u32,
@@ -900,8 +2606,10 @@ pub unsafe extern "C-unwind" fn WTInfoW(
     // WHAT
     // info!("WTInfoW({}, {}, {:#?});", w_category, n_index, lp_output);
     debug!("WTInfoW({}, {}, {:#?});", w_category, n_index, lp_output);
-    unsafe { WTInfo(w_category, n_index, lp_output) }
+    unsafe { info(w_category, n_index, Charset::Wide, lp_output) }
 }
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
 #[unsafe(no_mangle)]
 pub unsafe extern "C-unwind" fn WTInfo(
     w_category: u32,
@@ -909,42 +2617,583 @@ pub unsafe extern "C-unwind" fn WTInfo(
     lp_output: *mut c_void,
 ) -> u32 {
     debug!("WTInfo({}, {}, {:#?});", w_category, n_index, lp_output);
-
+    unsafe { info(w_category, n_index, Charset::Wide, lp_output) }
+}
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn info(category: u32, index: u32, charset: Charset, lp_output: *mut c_void) -> u32 {
     unsafe {
-        match w_category {
+        match category {
             // If the wCategory argument is zero, the function copies no data to the output buffer,
             // but returns the size in bytes of the buffer necessary to hold the largest complete category.
-            0 => size_of::<WtiLogicalContext>() as u32,
-            WTI_INTERFACE => WtiInterface::psm_default().handle_info(n_index, lp_output),
-
-            WTI_DEFCONTEXT => handle_logctx(n_index, lp_output, false),
-            WTI_DEFSYSCTX => handle_logctx(n_index, lp_output, true),
-            // WTI_STATUS => todo!(),
-            WTI_DEVICES => handle_device(n_index, lp_output),
-            WTI_CURSORS => handle_cursor(n_index, lp_output),
-            // WTI_EXTENSIONS => todo!(),
-            WTI_DDCTXS => handle_logctx(n_index, lp_output, false),
-            WTI_DSCTXS => handle_logctx(n_index, lp_output, true),
+            0 => largest_category(charset),
+            WTI_INTERFACE => handle_interface(index, charset, lp_output),
+
+            WTI_DEFCONTEXT => handle_logctx(0, false, index, charset, lp_output),
+            WTI_DEFSYSCTX => handle_logctx(0, true, index, charset, lp_output),
+            WTI_STATUS => handle_status(index, lp_output),
+            c if (WTI_DEVICES..WTI_CURSORS).contains(&c) => {
+                handle_device(c - WTI_DEVICES, index, charset, lp_output)
+            }
+            c if (WTI_CURSORS..WTI_EXTENSIONS).contains(&c) => {
+                handle_cursor(c - WTI_CURSORS, index, charset, lp_output)
+            }
+            c if (WTI_EXTENSIONS..WTI_DDCTXS).contains(&c) => {
+                handle_extension(c - WTI_EXTENSIONS, index, charset, lp_output)
+            }
+            c if (WTI_DDCTXS..WTI_DSCTXS).contains(&c) => {
+                handle_logctx(c - WTI_DDCTXS, false, index, charset, lp_output)
+            }
+            c if (WTI_DSCTXS..WTI_DSCTXS + 100).contains(&c) => {
+                handle_logctx(c - WTI_DSCTXS, true, index, charset, lp_output)
+            }
             _ => 0,
         }
     }
 }
 
-pub unsafe fn handle_logctx(index: u32, lp_output: *mut c_void, system: bool) -> u32 {
-    let mut state = get_state_or_init().unwrap();
-    let state = state.as_mut().unwrap();
-    state.default_context.options = if system { CXO_SYSTEM } else { 0 };
-    unsafe { state.default_context.handle_info(index, lp_output) }
+/// Size of the biggest category with index 0, strings encoded in `charset`.
+pub fn largest_category(charset: Charset) -> u32 {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    let size = |x: Option<Vec<u8>>| x.map_or(0, |x| x.len());
+    let devices = state.devices.iter().map(|x| size(x.info(0, charset)));
+    let cursors = state.cursors.iter().map(|x| size(x.info(0, charset)));
+    let contexts = state
+        .default_contexts
+        .values()
+        .map(|x| size(x.info(0, charset)));
+    [
+        size(state.interface().info(0, charset)),
+        size_of::<WtiStatus>(),
+    ]
+    .into_iter()
+    .chain(devices)
+    .chain(cursors)
+    .chain(contexts)
+    .max()
+    .unwrap_or(0) as u32
+}
+
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn handle_logctx(
+    device: u32,
+    system: bool,
+    index: u32,
+    charset: Charset,
+    lp_output: *mut c_void,
+) -> u32 {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    match state.default_contexts.get(&(device, system)) {
+        Some(lc) => unsafe { lc.handle_info(index, charset, lp_output) },
+        None => 0,
+    }
+}
+
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn handle_status(index: u32, lp_output: *mut c_void) -> u32 {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    let mut status = WtiStatus {
+        pkt_rate: STATS.packet_rate(),
+        managers: state.managers.len() as u32,
+        system: state.config.system_cursor.enabled as u32,
+        ..Default::default()
+    };
+    let mut psm = PsmStatus {
+        received: STATS.received.get(),
+        delivered: STATS.delivered.get(),
+        dropped_overflow: STATS.dropped_overflow.get(),
+        dropped_out_of_range: STATS.dropped_out_of_range.get(),
+        ..Default::default()
+    };
+    for device in 0..state.devices.len() as u32 {
+        if let Some(cursor) = state.cursors.get(state.active_cursor(device) as usize) {
+            status.sys_btn_use |= cursor.system_buttons_used();
+        }
+    }
+    // default contexts handed to managers aren't opened by anyone
+    for ctx in state.order.iter().filter_map(|x| state.contexts.get(x)) {
+        let lc = &ctx.logical_context;
+        status.contexts += 1;
+        if lc.options & CXO_SYSTEM != 0 {
+            status.sys_contexts += 1;
+        }
+        if lc.options & CXO_PEN != 0 {
+            psm.pen_contexts += 1;
+        }
+        status.pkt_data |= lc.packet_data;
+        status.button_use |= lc.btn_dn_mask | lc.btn_up_mask;
+    }
+    if index >= STA_PSM_PENCTXS {
+        return unsafe { psm.handle_info(index, lp_output) };
+    }
+    unsafe { status.handle_info(index, lp_output) }
 }
 
-pub unsafe fn handle_device(index: u32, lp_output: *mut c_void) -> u32 {
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn handle_interface(index: u32, charset: Charset, lp_output: *mut c_void) -> u32 {
     let state = get_state_or_init().unwrap();
     let state = state.as_ref().unwrap();
-    unsafe { state.device.handle_info(index, lp_output) }
+    unsafe { state.interface().handle_info(index, charset, lp_output) }
 }
 
-pub unsafe fn handle_cursor(index: u32, lp_output: *mut c_void) -> u32 {
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn handle_device(
+    device: u32,
+    index: u32,
+    charset: Charset,
+    lp_output: *mut c_void,
+) -> u32 {
     let state = get_state_or_init().unwrap();
     let state = state.as_ref().unwrap();
-    unsafe { state.cursor.handle_info(index, lp_output) }
+    match state.devices.get(device as usize) {
+        Some(device) => unsafe { device.handle_info(index, charset, lp_output) },
+        None => 0,
+    }
+}
+
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn handle_cursor(
+    cursor: u32,
+    index: u32,
+    charset: Charset,
+    lp_output: *mut c_void,
+) -> u32 {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    match state.cursors.get(cursor as usize) {
+        Some(cursor) => unsafe { cursor.handle_info(index, charset, lp_output) },
+        None => 0,
+    }
+}
+
+/// # Safety
+/// `lp_output` must be null or valid for writing the requested item.
+pub unsafe fn handle_extension(
+    extension: u32,
+    index: u32,
+    charset: Charset,
+    lp_output: *mut c_void,
+) -> u32 {
+    let state = get_state_or_init().unwrap();
+    let state = state.as_ref().unwrap();
+    match state.extensions.get(extension as usize) {
+        Some(ext) => unsafe { ext.handle_info(index, charset, lp_output) },
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MouseButton;
+
+    /// State for the psm.json shipped with PSM, after `patch` edits the config.
+    pub(crate) fn test_state(patch: impl FnOnce(&mut serde_json::Value)) -> PSM {
+        let mut config = serde_json::from_str(include_str!("../../psm.json")).unwrap();
+        patch(&mut config);
+        PSM::new(serde_json::from_value(config).unwrap())
+    }
+
+    /// Only the listed windows exist.
+    struct MockWindows(Vec<HWND>);
+    impl WindowTracker for MockWindows {
+        fn is_alive(&self, hwnd: HWND) -> bool {
+            self.0.contains(&hwnd)
+        }
+    }
+
+    #[test]
+    fn system_cursor_resets_between_contexts() {
+        let mut state = test_state(|config| {
+            config["system_cursor"]["without_context"] = true.into();
+        });
+        let pressed = Packet {
+            buttons: 1,
+            ..Default::default()
+        };
+        state.move_system_cursor(0, None, &pressed);
+        let down = CursorEvent::Button(MouseButton::Left, true);
+        assert!(state.cursor_events.contains(&down));
+
+        let mut context = Context::new(0, true);
+        context.logical_context.options |= CXO_SYSTEM;
+        let handle = state.open_context(HWND(0x10 as *mut c_void), context);
+        state.cursor_events.clear();
+        state.move_system_cursor(0, Some(handle), &pressed);
+        // the button pressed without a context is released before the context presses it again
+        let up = CursorEvent::Button(MouseButton::Left, false);
+        let buttons = state
+            .cursor_events
+            .iter()
+            .filter(|x| matches!(x, CursorEvent::Button(..)));
+        assert!(buttons.eq(&[up, down]));
+    }
+
+    #[test]
+    fn system_cursor_disabled_without_context() {
+        let mut state = test_state(|config| {
+            config["system_cursor"]["enabled"] = false.into();
+            config["system_cursor"]["without_context"] = true.into();
+        });
+        let pressed = Packet {
+            buttons: 1,
+            ..Default::default()
+        };
+        state.move_system_cursor(0, None, &pressed);
+        assert!(state.cursor_events.is_empty());
+    }
+
+    #[test]
+    fn reaps_contexts_of_destroyed_windows() {
+        let mut state = test_state(|_| {});
+        let alive = HWND(0x10 as *mut c_void);
+        let destroyed = HWND(0x20 as *mut c_void);
+        let kept = state.open_context(alive, Context::new(0, true));
+        let orphan = state.open_context(destroyed, Context::new(0, true));
+        // default contexts have no window and are never reaped
+        let default = state.default_context_handle(0, false).unwrap();
+
+        let windows = MockWindows(vec![alive]);
+        assert_eq!(state.reap_orphans(&windows), [orphan]);
+        assert!(!state.contexts.contains_key(&orphan));
+        assert!(state.contexts.contains_key(&kept));
+        assert!(state.contexts.contains_key(&default));
+        assert_eq!(state.order, [kept]);
+        assert!(state.reap_orphans(&windows).is_empty());
+    }
+
+    #[test]
+    fn lifecycle_messages_follow_options() {
+        let mut state = test_state(|_| {});
+        window::take_posted();
+        let posted = || {
+            window::take_posted()
+                .into_iter()
+                .map(|(hwnd, message, wparam, lparam)| {
+                    (hwnd.0 as usize, message - WT_DEFBASE, wparam.0, lparam.0)
+                })
+                .collect::<Vec<_>>()
+        };
+        let (open, close, overlap, csr_change) = (1, 2, 4, 7);
+        let with_options = |options| {
+            let mut ctx = Context::new(0, true);
+            ctx.logical_context.options = options;
+            ctx
+        };
+        let quiet = state.open_context(HWND(0x10 as *mut c_void), with_options(0));
+        // WT_CTXOPEN comes after ordering, with CXS_ONTOP
+        let ontop = CXS_ONTOP as isize;
+        assert_eq!(posted(), [(0x10, open, quiet, ontop)]);
+        let options = CXO_MESSAGES | CXO_CSRMESSAGES;
+        let chatty = state.open_context(HWND(0x20 as *mut c_void), with_options(options));
+        let obscured = CXS_OBSCURED as isize;
+        assert_eq!(
+            posted(),
+            [
+                (0x10, overlap, quiet, obscured),
+                (0x20, open, chatty, ontop)
+            ]
+        );
+
+        // WT_PACKET and WT_CSRCHANGE only go to contexts that asked for them
+        for handle in [quiet, chatty] {
+            let ctx = state.contexts.get_mut(&handle).unwrap();
+            ctx.send_packet(Packet::default()).unwrap();
+            ctx.send_packet(Packet::default()).unwrap();
+        }
+        let packet = 0;
+        let chatty_param = chatty as isize;
+        assert_eq!(
+            posted(),
+            [
+                (0x20, csr_change, 1, chatty_param),
+                (0x20, packet, 1, chatty_param),
+                (0x20, packet, 2, chatty_param),
+            ]
+        );
+
+        state.close_context(chatty, true);
+        assert_eq!(
+            posted(),
+            [(0x20, close, chatty, ontop), (0x10, overlap, quiet, ontop)]
+        );
+        // reaped contexts have no window to post to
+        state.close_context(quiet, false);
+        assert!(posted().is_empty());
+    }
+
+    #[test]
+    fn null_buffer_discards_packets() {
+        let handle = {
+            let mut state = STATE.lock().unwrap();
+            let state = state.get_or_insert_with(|| test_state(|_| {}));
+            let mut ctx = Context::new(0, true);
+            for serial in 1..=3 {
+                let mut packet = PacketExt::default();
+                packet.base.serial = serial;
+                ctx.ext_packets.push_back(packet);
+            }
+            ctx.packets.push_back(Packet {
+                serial: 4,
+                ..Default::default()
+            });
+            state.open_context(HWND(0x10 as *mut c_void), ctx)
+        };
+        let discard = |serial| unsafe { packet(handle, serial, std::ptr::null_mut()).unwrap() };
+        assert!(discard(2));
+        assert!(discard(4));
+        assert!(!discard(4));
+        let mut state = STATE.lock().unwrap();
+        let ctx = &state.as_mut().unwrap().contexts[&handle];
+        assert!(ctx.ext_packets.iter().map(|x| x.base.serial).eq([3]));
+        assert!(ctx.packets.is_empty());
+    }
+
+    #[test]
+    fn packets_go_to_contexts_on_their_device() {
+        let mut state = test_state(|config| {
+            config["extra_devices"] = serde_json::json!([config["preset"].clone()]);
+            config["extra_devices"][0]["name"] = "Small tablet".into();
+        });
+        assert_eq!(state.interface().num_devices, 2);
+        assert_eq!(state.devices[0].name, "PSMDEV1");
+        assert_eq!(state.devices[1].name, "Small tablet");
+
+        let on_device = |device| {
+            let mut ctx = Context::new(0, true);
+            ctx.logical_context.device = device;
+            ctx
+        };
+        let display = state.open_context(HWND(0x10 as *mut c_void), on_device(0));
+        let tablet = state.open_context(HWND(0x20 as *mut c_void), on_device(1));
+        let unknown = state.open_context(HWND(0x30 as *mut c_void), on_device(2));
+        assert_eq!(unknown, 0);
+
+        // the tablet's context is on top, but the display only feeds its own
+        assert_eq!(state.order, [tablet, display]);
+        assert_eq!(state.top_context(0).map(|x| x.handle), Some(display));
+        assert_eq!(state.top_context(1).map(|x| x.handle), Some(tablet));
+        assert!(state.top_context(2).is_none());
+    }
+
+    #[test]
+    fn cursor_table_follows_devices() {
+        let mut state = test_state(|config| {
+            config["extra_devices"] = serde_json::json!([config["preset"].clone()]);
+            config["extra_devices"][0]["cursors"] = serde_json::json!([
+                { "name": "Grip Pen", "kind": "pen", "buttons": 2, "physical_id": 7 },
+                { "name": "Grip Pen Eraser", "kind": "eraser", "buttons": 2, "physical_id": 7 },
+            ]);
+        });
+        assert_eq!(state.interface().num_cursors, 5);
+        let ranges = state
+            .devices
+            .iter()
+            .map(|x| (x.first_cursor_type, x.num_cursor_types))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(0, 3), (3, 2)]);
+
+        let eraser = &state.cursors[2];
+        assert_eq!(eraser.name, "Eraser");
+        assert_eq!(eraser.capabilities, CRC_MULTIMODE | CRC_INVERT);
+        assert_eq!(eraser.csr_mode, 1);
+        assert_eq!(eraser.physical_id, state.cursors[1].physical_id);
+        assert_eq!(state.cursors[0].packet_data & PK_NORMAL_PRESSURE, 0);
+
+        // the puck is skipped, the eraser follows the pen
+        assert_eq!(state.active_cursor(0), 1);
+        assert_eq!(state.active_cursor(1), 3);
+        state.eraser = true;
+        assert_eq!(state.active_cursor(0), 2);
+        assert_eq!(state.active_cursor(1), 4);
+    }
+
+    #[test]
+    fn identity_strings_come_from_config() {
+        let state = test_state(|config| {
+            config["wintab_id"] = "WACOM Tablet".into();
+            config["preset"]["name"] = "Wacom Cintiq Pro 24 Pen".into();
+            config["preset"]["pnp_id"] = "WACF004".into();
+        });
+        assert_eq!(state.interface().wintabid, "WACOM Tablet");
+        let device = &state.devices[0];
+        assert_eq!(
+            device.item(1, Charset::Ansi).unwrap(),
+            b"Wacom Cintiq Pro 24 Pen\0"
+        );
+        assert_eq!(device.item(19, Charset::Ansi).unwrap(), b"WACF004\0");
+    }
+
+    #[test]
+    fn buttons_follow_cursor_definitions() {
+        let state = test_state(|config| {
+            config["preset"]["cursors"] = serde_json::json!([{
+                "name": "Airbrush",
+                "kind": "pen",
+                "buttons": 3,
+                "button_names": ["Tip", "Side Switch"],
+                "button_map": [0, 2, 1],
+                "system_buttons": ["left_drag", "none", "right_double_click"],
+            }]);
+        });
+        let cursor = &state.cursors[0];
+        assert_eq!(
+            cursor.item(6, Charset::Ansi).unwrap(),
+            b"Tip\0Side Switch\0Button 3\0\0"
+        );
+        assert_eq!(cursor.item(7, Charset::Ansi).unwrap()[..4], [0, 2, 1, 3]);
+        let system = cursor.item(8, Charset::Ansi).unwrap();
+        assert_eq!(system[..4], [SBN_LDRAG, SBN_NONE, SBN_RDBLCLICK, SBN_NONE]);
+        assert_eq!(cursor.logical_buttons(0b011), 0b101);
+        assert_eq!(cursor.logical_buttons(0b100), 0b010);
+        assert_eq!(cursor.system_buttons_used(), 0b101);
+
+        // logical buttons are bits of a DWORD
+        let cursor = serde_json::json!({
+            "name": "Pen",
+            "kind": "pen",
+            "buttons": 2,
+            "button_map": [0, 32],
+        });
+        let err = serde_json::from_value::<config::CursorConfig>(cursor).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("logical button 32 is out of range")
+        );
+    }
+
+    /// Error of a pen whose config has the `pressure` curve.
+    fn pressure_error(pressure: serde_json::Value) -> String {
+        let cursor = serde_json::json!({
+            "name": "Pen",
+            "kind": "pen",
+            "buttons": 2,
+            "pressure": pressure,
+        });
+        let err = serde_json::from_value::<config::CursorConfig>(cursor).unwrap_err();
+        err.to_string()
+    }
+
+    #[test]
+    fn pressure_rejects_empty_range() {
+        let err = pressure_error(serde_json::json!({ "min": 0.5, "max": 0.5 }));
+        assert!(
+            err.contains("pressure min 0.5 isn't below max 0.5"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn pressure_rejects_nonpositive_gamma() {
+        let err = pressure_error(serde_json::json!({ "gamma": 0.0 }));
+        assert!(err.contains("pressure gamma 0 isn't positive"), "{err}");
+    }
+
+    #[test]
+    fn pressure_rejects_points_outside_unit_square() {
+        let err = pressure_error(serde_json::json!({ "points": [[0.5, 1.5]] }));
+        assert!(err.contains("is out of range (0-1)"), "{err}");
+    }
+
+    #[test]
+    fn pressure_rejects_unordered_points() {
+        let points = [[0.2, 0.1], [0.6, 0.5], [0.6, 0.7]];
+        let err = pressure_error(serde_json::json!({ "points": points }));
+        assert!(
+            err.contains("pressure point [0.6, 0.7] doesn't come after [0.6, 0.5]"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn pressure_rejects_release_above_press() {
+        let err = pressure_error(serde_json::json!({ "button_marks": [0.3, 0.2] }));
+        assert!(
+            err.contains("release mark 0.3 is above the press mark 0.2"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn pressure_follows_cursor_curve() {
+        let mut state = test_state(|config| {
+            config["preset"]["normal_pressure"]["min"] = 0.into();
+            config["preset"]["normal_pressure"]["max"] = 1000.into();
+            config["preset"]["cursors"] = serde_json::json!([{
+                "name": "Pen",
+                "kind": "pen",
+                "buttons": 2,
+                "button_map": [1, 0],
+                "pressure": { "gamma": 2.0, "button_marks": [0.1, 0.4] },
+            }]);
+        });
+        let cursor = &state.cursors[0];
+        assert_eq!(cursor.npbtnmarks, [100, 400]);
+        assert_eq!(cursor.npresponse[255], 1000);
+        let response = cursor.item(11, Charset::Ansi).unwrap();
+        assert_eq!(response.len(), 256 * 4);
+
+        // the tip is the physical button 0, which maps to the logical button 1
+        assert_eq!(state.apply_cursor(0, 0, 0, 300), (0, 90));
+        assert_eq!(state.apply_cursor(0, 0, 0, 500), (0b10, 250));
+        assert_eq!(state.apply_cursor(0, 0, 0, 300), (0b10, 90));
+        assert_eq!(state.apply_cursor(0, 0, 0b01, 100), (0, 10));
+    }
+
+    #[test]
+    fn default_contexts_are_per_device() {
+        let mut state = test_state(|config| {
+            config["extra_devices"] = serde_json::json!([config["preset"].clone()]);
+            config["extra_devices"][0]["in_ext_x"] = 1234.into();
+        });
+        assert_eq!(state.default_contexts.len(), 4);
+        for ((device, system), lc) in state.default_contexts.iter() {
+            assert_eq!(lc.device, *device);
+            assert_eq!(lc.options & CXO_SYSTEM != 0, *system);
+        }
+        assert_eq!(state.default_contexts[&(1, false)].in_ext_x, 1234);
+        assert_ne!(state.default_contexts[&(0, false)].in_ext_x, 1234);
+
+        let handle = state.default_context_handle(1, true).unwrap();
+        let lc = &state.contexts[&handle].logical_context;
+        assert_eq!((lc.device, lc.options & CXO_SYSTEM), (1, CXO_SYSTEM));
+        assert_eq!(state.default_context_key(handle), Some((1, true)));
+        assert_eq!(state.default_context_handle(2, false), None);
+    }
+
+    #[test]
+    fn info_changes_are_per_item() {
+        let mut state = test_state(|_| {});
+        let before = state.info_items();
+        assert!(info_changes(&before, &state.info_items()).is_empty());
+
+        state.devices[0].name = "Renamed".into();
+        state.cursors[1].name = "Renamed Pen".into();
+        state.cursors[1].set_axes(
+            &Axis {
+                max: 1023,
+                ..Axis::psm_default()
+            },
+            &Axis::psm_default(),
+        );
+        let changes = info_changes(&before, &state.info_items());
+        assert_eq!(
+            changes,
+            [
+                (WTI_DEVICES, 1),
+                (WTI_CURSORS + 1, 1),
+                (WTI_CURSORS + 1, 10),
+                (WTI_CURSORS + 1, 11),
+            ]
+        );
+        assert_eq!(info_change_lparam(WTI_CURSORS + 1, 11), LPARAM(0x000b_00c9));
+    }
 }
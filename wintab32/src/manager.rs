@@ -0,0 +1,27 @@
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+
+use crate::{ThreadHWND, ffi::WindowMessage, window::post_message};
+
+/// Maximum number of manager handles open at once, reported as `IFC_NMANAGERS`.
+pub const MAX_MANAGERS: u32 = 8;
+
+/// WTMgrDeviceConfig: the device has no configuration dialog.
+pub const WTDC_NONE: u32 = 0;
+
+/// A manager handle opened by WTMgrOpen.
+/// Managers receive context lifecycle, proximity and info change messages for every context.
+pub struct Manager {
+    pub handle: usize,
+    pub window: ThreadHWND,
+    pub msg_base: u32,
+}
+impl Manager {
+    pub fn post(
+        &self,
+        message: WindowMessage,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> color_eyre::Result<()> {
+        post_message(self.window.0, message.value(self.msg_base), wparam, lparam)
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{config::PressureConfig, ffi::Axis};
+
+/// Number of samples reported through CSR_NPRESPONSE and CSR_TPRESPONSE.
+pub const RESPONSE_SAMPLES: usize = 256;
+
+/// A cursor's pressure response over one pressure axis of its device.
+///
+/// Raw client pressure is taken as a fraction of the axis, cut at the thresholds,
+/// bent by the control points or gamma, and scaled back onto the axis.
+#[derive(Debug, Clone)]
+pub struct PressureCurve {
+    config: PressureConfig,
+    min: i64,
+    max: i64,
+    /// Raw button marks set by a manager, kept over [PressureConfig::button_marks].
+    marks: Option<[u32; 2]>,
+}
+impl PressureCurve {
+    pub fn new(config: &PressureConfig, axis: &Axis) -> Self {
+        Self {
+            config: config.clone(),
+            min: axis.min as i64,
+            max: axis.max as i64,
+            marks: None,
+        }
+    }
+
+    /// The same curve over another axis.
+    pub fn with_axis(&self, axis: &Axis) -> Self {
+        Self {
+            marks: self.marks,
+            ..Self::new(&self.config, axis)
+        }
+    }
+
+    /// The same curve with raw button marks, like a manager's CSR_NPBTNMARKS.
+    pub fn with_button_marks(&self, marks: [u32; 2]) -> Self {
+        Self {
+            marks: Some(marks),
+            ..self.clone()
+        }
+    }
+
+    /// A piecewise linear curve through `response`, samples over the whole axis like [PressureCurve::response].
+    /// The thresholds are dropped, the button marks are kept.
+    pub fn with_response(&self, response: &[u32; RESPONSE_SAMPLES]) -> Self {
+        let range = (self.max - self.min).max(1) as f64;
+        let points = response
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let fraction = (*x as i64 - self.min) as f64 / range;
+                [
+                    i as f64 / (RESPONSE_SAMPLES - 1) as f64,
+                    fraction.clamp(0.0, 1.0),
+                ]
+            })
+            .collect();
+        let config = PressureConfig {
+            points,
+            button_marks: self.config.button_marks,
+            ..Default::default()
+        };
+        Self { config, ..*self }
+    }
+
+    /// Raw pressure reported as is.
+    pub fn linear(axis: &Axis) -> Self {
+        Self::new(&PressureConfig::default(), axis)
+    }
+
+    /// Pressure apps get for raw client pressure.
+    pub fn apply(&self, raw: u32) -> u32 {
+        let range = self.max - self.min;
+        if range <= 0 {
+            return raw;
+        }
+        let fraction = (raw as i64 - self.min) as f64 / range as f64;
+        self.to_axis(self.curve(fraction.clamp(0.0, 1.0)))
+    }
+
+    /// Samples of the curve over the whole axis, like CSR_NPRESPONSE.
+    pub fn response(&self) -> [u32; RESPONSE_SAMPLES] {
+        let mut out = [0u32; RESPONSE_SAMPLES];
+        for (i, value) in out.iter_mut().enumerate() {
+            *value = self.to_axis(self.curve(i as f64 / (RESPONSE_SAMPLES - 1) as f64));
+        }
+        out
+    }
+
+    /// Raw pressures of the release and press marks, like CSR_NPBTNMARKS.
+    pub fn button_marks(&self) -> [u32; 2] {
+        self.marks
+            .unwrap_or_else(|| self.config.button_marks.map(|x| self.to_axis(x)))
+    }
+
+    /// Response to a fraction of the axis, as a fraction of the axis.
+    fn curve(&self, fraction: f64) -> f64 {
+        let PressureConfig { min, max, .. } = self.config;
+        if fraction <= min {
+            return 0.0;
+        }
+        if fraction >= max {
+            return 1.0;
+        }
+        let t = (fraction - min) / (max - min);
+        if self.config.points.is_empty() {
+            return t.powf(self.config.gamma);
+        }
+        // a piecewise linear curve from (0, 0) through the points to (1, 1)
+        let mut last = (0.0, 0.0);
+        let points = self.config.points.iter().map(|[x, y]| (*x, *y));
+        for (x, y) in points.chain([(1.0, 1.0)]) {
+            if t <= x {
+                if x <= last.0 {
+                    return y;
+                }
+                return last.1 + (y - last.1) * (t - last.0) / (x - last.0);
+            }
+            last = (x, y);
+        }
+        1.0
+    }
+
+    fn to_axis(&self, fraction: f64) -> u32 {
+        let range = (self.max - self.min) as f64;
+        (self.min + (fraction.clamp(0.0, 1.0) * range).round() as i64).max(0) as u32
+    }
+}
+
+/// Whether the pressure button is down after a packet with `raw` pressure.
+/// It's pressed at the press mark and released at the release mark, keeping its state in between.
+pub fn button_pressed(pressed: bool, raw: u32, marks: [u32; 2]) -> bool {
+    let [release, press] = marks;
+    if raw >= press {
+        true
+    } else if raw <= release {
+        false
+    } else {
+        pressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axis() -> Axis {
+        Axis {
+            min: 0,
+            max: 1000,
+            ..Axis::psm_default()
+        }
+    }
+
+    fn config() -> PressureConfig {
+        PressureConfig::default()
+    }
+
+    #[test]
+    fn default_curve_is_linear() {
+        let curve = PressureCurve::linear(&axis());
+        assert_eq!(curve.apply(0), 0);
+        assert_eq!(curve.apply(437), 437);
+        assert_eq!(curve.apply(1000), 1000);
+        let response = curve.response();
+        assert_eq!(response[0], 0);
+        assert_eq!(response[51], 200);
+        assert_eq!(response[255], 1000);
+    }
+
+    #[test]
+    fn thresholds_cut_the_ends() {
+        let curve = PressureCurve::new(
+            &PressureConfig {
+                min: 0.1,
+                max: 0.6,
+                ..config()
+            },
+            &axis(),
+        );
+        assert_eq!(curve.apply(50), 0);
+        assert_eq!(curve.apply(100), 0);
+        assert_eq!(curve.apply(350), 500);
+        assert_eq!(curve.apply(600), 1000);
+        assert_eq!(curve.apply(900), 1000);
+    }
+
+    #[test]
+    fn gamma_and_points_bend_the_curve() {
+        let gamma = PressureCurve::new(
+            &PressureConfig {
+                gamma: 2.0,
+                ..config()
+            },
+            &axis(),
+        );
+        assert_eq!(gamma.apply(500), 250);
+        let points = PressureCurve::new(
+            &PressureConfig {
+                points: vec![[0.5, 0.2]],
+                ..config()
+            },
+            &axis(),
+        );
+        assert_eq!(points.apply(250), 100);
+        assert_eq!(points.apply(500), 200);
+        assert_eq!(points.apply(750), 600);
+        // the reported response is what gets applied
+        assert_eq!(points.response()[255], points.apply(1000));
+        let replaced = PressureCurve::linear(&axis()).with_response(&points.response());
+        assert_eq!(replaced.response(), points.response());
+        assert_eq!(replaced.apply(250), 100);
+    }
+
+    #[test]
+    fn manager_button_marks_are_kept() {
+        let curve = PressureCurve::linear(&axis());
+        assert_eq!(curve.button_marks(), [10, 30]);
+        let curve = curve.with_button_marks([100, 200]);
+        assert_eq!(curve.button_marks(), [100, 200]);
+        let wider = Axis {
+            max: 4000,
+            ..axis()
+        };
+        assert_eq!(curve.with_axis(&wider).button_marks(), [100, 200]);
+        let response = curve.response();
+        assert_eq!(curve.with_response(&response).button_marks(), [100, 200]);
+    }
+
+    #[test]
+    fn button_has_hysteresis() {
+        let marks = [20, 50];
+        let states = [0, 30, 50, 30, 20, 30]
+            .iter()
+            .scan(false, |pressed, raw| {
+                *pressed = button_pressed(*pressed, *raw, marks);
+                Some(*pressed)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(states, [false, false, true, true, false, false]);
+    }
+}
@@ -0,0 +1,287 @@
+use psm_common::netcode::Axis;
+
+use crate::{
+    config::{Config, SliderConfig, default_cursors},
+    ffi::{HWC_HARDPROX, HWC_INTEGRATED, HWC_PHYSID_CURSORS, TU_CENTIMETERS, TU_CIRCLE},
+};
+
+/// Lines per centimeter of Wacom's 5080 lpi digitizers.
+const LINES_PER_CM: u32 = 2000;
+/// Positions of a Wacom touch ring.
+const TOUCH_RING_MAX: u32 = 71;
+
+/// Descriptors of a real tablet, for apps that only behave with a Wacom-like tablet.
+/// Selected with `profile` in psm.json or by the client, replacing the identity strings,
+/// axes, cursors and extensions of the main device. Context output and screen areas are kept.
+#[derive(Debug)]
+pub struct Profile {
+    /// Name used to select the profile, e.g. "intuos-pro-m".
+    pub name: &'static str,
+    /// IFC_WINTABID.
+    pub wintab_id: &'static str,
+    /// DVC_NAME.
+    pub device_name: &'static str,
+    /// DVC_PNPID, the model number for USB tablets.
+    pub pnp_id: &'static str,
+    /// Active area in lines, at 200 lines per millimeter. (X)
+    pub width: i32,
+    /// Active area in lines, at 200 lines per millimeter. (Y)
+    pub height: i32,
+    /// Levels of normal pressure.
+    pub pressure_levels: i32,
+    /// Whether the pen reports tilt as orientation.
+    pub tilt: bool,
+    /// Whether an airbrush wheel reports tangential pressure.
+    pub airbrush: bool,
+    /// Pen display, the digitizer is over the screen (HWC_INTEGRATED).
+    pub integrated: bool,
+    pub express_keys: u32,
+    /// Where the ExpressKeys are: 0 - left, 1 - right, 2 - top, 3 - bottom.
+    pub express_keys_location: u8,
+    /// Modes of the touch ring, no ring if 0.
+    pub touch_ring_modes: u32,
+}
+
+pub static PROFILES: [Profile; 4] = [
+    Profile {
+        name: "intuos-s",
+        wintab_id: "WACOM Tablet",
+        device_name: "Wacom Intuos S Pen",
+        pnp_id: "CTL-4100",
+        width: 30400,
+        height: 19000,
+        pressure_levels: 4096,
+        tilt: false,
+        airbrush: false,
+        integrated: false,
+        express_keys: 4,
+        express_keys_location: 2,
+        touch_ring_modes: 0,
+    },
+    Profile {
+        name: "intuos-pro-m",
+        wintab_id: "WACOM Tablet",
+        device_name: "Wacom Intuos Pro M Pen",
+        pnp_id: "PTH-660",
+        width: 44800,
+        height: 29600,
+        pressure_levels: 8192,
+        tilt: true,
+        airbrush: true,
+        integrated: false,
+        express_keys: 8,
+        express_keys_location: 0,
+        touch_ring_modes: 4,
+    },
+    Profile {
+        name: "intuos-pro-l",
+        wintab_id: "WACOM Tablet",
+        device_name: "Wacom Intuos Pro L Pen",
+        pnp_id: "PTH-860",
+        width: 62200,
+        height: 43200,
+        pressure_levels: 8192,
+        tilt: true,
+        airbrush: true,
+        integrated: false,
+        express_keys: 8,
+        express_keys_location: 0,
+        touch_ring_modes: 4,
+    },
+    Profile {
+        name: "cintiq-16",
+        wintab_id: "WACOM Tablet",
+        device_name: "Wacom Cintiq 16 Pen",
+        pnp_id: "DTK-1660",
+        width: 68832,
+        height: 38718,
+        pressure_levels: 8192,
+        tilt: true,
+        airbrush: false,
+        integrated: true,
+        express_keys: 0,
+        express_keys_location: 0,
+        touch_ring_modes: 0,
+    },
+];
+
+/// The profile called `name`.
+pub fn find(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|x| x.name == name)
+}
+
+impl Profile {
+    /// Replaces the main device, its identity and the extensions in `config` with this profile.
+    pub fn apply(&self, config: &mut Config) {
+        let lines = |max: i32| Axis {
+            min: 0,
+            max,
+            units: TU_CENTIMETERS,
+            resolution: LINES_PER_CM << 16,
+        };
+        let unsupported = Axis {
+            min: 0,
+            max: 0,
+            units: 0,
+            resolution: 0,
+        };
+        let circle = |min: i32, max: i32| Axis {
+            min,
+            max,
+            units: TU_CIRCLE,
+            resolution: 3600 << 16,
+        };
+
+        config.wintab_id = self.wintab_id.into();
+        let preset = &mut config.preset;
+        preset.name = Some(self.device_name.into());
+        preset.pnp_id = self.pnp_id.into();
+        preset.hardware = HWC_HARDPROX | HWC_PHYSID_CURSORS;
+        if self.integrated {
+            preset.hardware |= HWC_INTEGRATED;
+        }
+        preset.packet_rate = 200;
+        preset.cursors = default_cursors();
+        preset.device_x = lines(self.width - 1);
+        preset.device_y = lines(self.height - 1);
+        preset.device_z = unsupported;
+        preset.normal_pressure = Axis {
+            max: self.pressure_levels - 1,
+            ..unsupported
+        };
+        preset.tangential_pressure = if self.airbrush {
+            Axis {
+                max: 1023,
+                ..unsupported
+            }
+        } else {
+            unsupported
+        };
+        // azimuth and altitude in tenths of a degree, no twist
+        preset.orientation = if self.tilt {
+            [circle(0, 3599), circle(-900, 900), unsupported]
+        } else {
+            [unsupported; 3]
+        };
+        preset.rotation = [unsupported; 3];
+        // the default contexts cover the whole tablet in its own coordinates
+        (preset.in_org_x, preset.in_org_y, preset.in_org_z) = (0, 0, 0);
+        (preset.in_ext_x, preset.in_ext_y, preset.in_ext_z) = (self.width, self.height, 0);
+        (preset.out_org_x, preset.out_org_y, preset.out_org_z) = (0, 0, 0);
+        (preset.out_ext_x, preset.out_ext_y, preset.out_ext_z) = (self.width, self.height, 0);
+
+        let extensions = &mut config.extensions;
+        extensions.express_keys = self.express_keys;
+        extensions.express_keys_location = self.express_keys_location;
+        extensions.touch_strips = Vec::new();
+        extensions.touch_rings = if self.touch_ring_modes == 0 {
+            Vec::new()
+        } else {
+            vec![SliderConfig {
+                modes: self.touch_ring_modes,
+                max: TOUCH_RING_MAX,
+            }]
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ffi::{CRC_INVERT, PK_NORMAL_PRESSURE},
+        tests::test_state,
+    };
+
+    #[test]
+    fn names_are_unique() {
+        for profile in PROFILES.iter() {
+            assert!(std::ptr::eq(find(profile.name).unwrap(), profile));
+        }
+        assert!(find("graphire-3").is_none());
+    }
+
+    #[test]
+    fn profiles_are_consistent() {
+        for profile in PROFILES.iter() {
+            let state = test_state(|config| config["profile"] = profile.name.into());
+            let name = profile.name;
+            assert_eq!(state.interface().wintabid, profile.wintab_id, "{name}");
+            assert_eq!(state.devices.len(), 1, "{name}");
+            let device = &state.devices[0];
+            assert_eq!(device.name, profile.device_name, "{name}");
+            assert_eq!(device.pnp_id, profile.pnp_id, "{name}");
+            assert_eq!(
+                device.hardware & HWC_INTEGRATED != 0,
+                profile.integrated,
+                "{name}"
+            );
+            assert_eq!(device.device_x.max + 1, profile.width, "{name}");
+            assert_eq!(device.device_y.max + 1, profile.height, "{name}");
+            let pressure_max = device.normal_pressure.max;
+            assert_eq!(pressure_max + 1, profile.pressure_levels, "{name}");
+            assert_eq!(device.orientation[0].max > 0, profile.tilt, "{name}");
+            assert_eq!(
+                device.tangential_pressure.max > 0,
+                profile.airbrush,
+                "{name}"
+            );
+
+            // default contexts map the whole tablet
+            for lc in state.default_contexts.values() {
+                assert_eq!(
+                    (lc.in_ext_x, lc.in_ext_y),
+                    (profile.width, profile.height),
+                    "{name}"
+                );
+                assert_eq!(lc.packet_rate, device.packet_rate, "{name}");
+            }
+
+            // every pen is followed by its eraser, and pressure fits the axis
+            let first = device.first_cursor_type as usize;
+            let cursors = &state.cursors[first..first + device.num_cursor_types as usize];
+            assert_eq!(cursors.len(), state.cursors.len(), "{name}");
+            for (i, cursor) in cursors.iter().enumerate() {
+                assert_eq!(cursor.button_names.len(), cursor.buttons as usize, "{name}");
+                if cursor.capabilities & CRC_INVERT != 0 {
+                    assert_eq!(cursor.physical_id, cursors[i - 1].physical_id, "{name}");
+                }
+                if cursor.packet_data & PK_NORMAL_PRESSURE != 0 {
+                    assert_eq!(cursor.npresponse[255] as i32, pressure_max, "{name}");
+                    let [release, press] = cursor.npbtnmarks;
+                    assert!(release < press && press as i32 <= pressure_max, "{name}");
+                }
+            }
+
+            // the pen and the eraser can both be picked
+            let mut state = state;
+            let pen = state.active_cursor(0) as usize;
+            state.eraser = true;
+            let eraser = state.active_cursor(0) as usize;
+            assert_eq!(state.cursors[pen].capabilities & CRC_INVERT, 0, "{name}");
+            assert_eq!(
+                state.cursors[eraser].capabilities & CRC_INVERT,
+                CRC_INVERT,
+                "{name}"
+            );
+
+            // ExpressKeys, touch strips and touch rings
+            let [keys, strips, rings] = &state.extensions[..] else {
+                panic!("{name} has the wrong extensions");
+            };
+            assert_eq!(keys.controls, profile.express_keys, "{name}");
+            assert_eq!(
+                keys.location, profile.express_keys_location as u32,
+                "{name}"
+            );
+            assert_eq!(strips.controls, 0, "{name}");
+            assert_eq!(
+                rings.controls,
+                (profile.touch_ring_modes > 0) as u32,
+                "{name}"
+            );
+            assert_eq!(rings.functions, profile.touch_ring_modes, "{name}");
+            assert_eq!(rings.axes.len(), rings.controls as usize, "{name}");
+        }
+    }
+}
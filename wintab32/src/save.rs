@@ -0,0 +1,234 @@
+use color_eyre::eyre::bail;
+
+use crate::{MAX_QUEUE_SIZE, charset::Charset, ffi::WtiLogicalContext};
+
+/// Marks a blob produced by [WTSave](crate::WTSave).
+pub const SAVE_MAGIC: [u8; 4] = *b"PSMC";
+/// Bump this when the blob layout changes.
+/// Version 1 stored the context as a LOGCONTEXTA, version 2 as a LOGCONTEXTW.
+pub const SAVE_VERSION: u16 = 2;
+/// Magic, version and reserved flags.
+pub const SAVE_HEADER_SIZE: usize = 8;
+/// Size of the save information, as reported by `IFC_CTXSAVESIZE`.
+pub const SAVE_SIZE: usize = save_size(Charset::Wide);
+
+/// Charset of the stored context name in each supported version.
+const fn version_charset(version: u16) -> Option<Charset> {
+    match version {
+        1 => Some(Charset::Ansi),
+        2 => Some(Charset::Wide),
+        _ => None,
+    }
+}
+
+const fn save_size(charset: Charset) -> usize {
+    SAVE_HEADER_SIZE
+        + WtiLogicalContext::size(charset)
+        // queue size
+        + 4
+        // checksum
+        + 4
+}
+
+/// Size of the blob starting with `header`, which must be at least [SAVE_HEADER_SIZE] long.
+/// Lets callers check the header before reading the rest of a blob of unknown length.
+pub fn blob_size(header: &[u8]) -> color_eyre::Result<usize> {
+    if header.len() < SAVE_HEADER_SIZE || header[..4] != SAVE_MAGIC {
+        bail!("not a PSM context save");
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    let Some(charset) = version_charset(version) else {
+        bail!(
+            "unsupported context save version {} (expected {})",
+            version,
+            SAVE_VERSION
+        );
+    };
+    Ok(save_size(charset))
+}
+
+/// Context state stored by WTSave and restored by WTRestore.
+///
+/// All fields are written one by one in little-endian, so the blob doesn't depend on
+/// the in-memory layout of [WtiLogicalContext].
+pub struct SavedContext {
+    pub logical_context: WtiLogicalContext,
+    pub queue_size: u32,
+}
+
+impl SavedContext {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(SAVE_SIZE);
+        out.extend_from_slice(&SAVE_MAGIC);
+        out.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        // reserved flags
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&self.logical_context.to_bytes(Charset::Wide));
+        out.extend_from_slice(&self.queue_size.to_le_bytes());
+        out.extend_from_slice(&checksum(&out).to_le_bytes());
+        debug_assert_eq!(out.len(), SAVE_SIZE);
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> color_eyre::Result<Self> {
+        let size = blob_size(data)?;
+        if data.len() < size {
+            bail!("context save is truncated");
+        }
+        let (body, sum) = data[..size].split_at(size - 4);
+        if checksum(body).to_le_bytes() != sum {
+            bail!("context save is corrupt (checksum mismatch)");
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        let charset = version_charset(version).unwrap();
+        let context_size = WtiLogicalContext::size(charset);
+        let context = &body[SAVE_HEADER_SIZE..SAVE_HEADER_SIZE + context_size];
+        let mut reader = Reader {
+            data: body,
+            pos: SAVE_HEADER_SIZE + context_size,
+        };
+        let logical_context = WtiLogicalContext::from_bytes(context, charset);
+        let queue_size = u32::from_le_bytes(reader.take::<4>());
+        if queue_size == 0 || queue_size as usize > MAX_QUEUE_SIZE {
+            bail!("context save has an invalid queue size {}", queue_size);
+        }
+        Ok(Self {
+            logical_context,
+            queue_size,
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(&self.data[self.pos..self.pos + N]);
+        self.pos += N;
+        buf
+    }
+}
+
+/// 32-bit FNV-1a.
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved() -> SavedContext {
+        let mut logical_context = WtiLogicalContext::psm_default();
+        logical_context.name = "Saved context".into();
+        logical_context.packet_rate = 75;
+        logical_context.out_ext_y = -1080;
+        SavedContext {
+            logical_context,
+            queue_size: 32,
+        }
+    }
+
+    fn error(data: &[u8]) -> String {
+        SavedContext::from_bytes(data).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let saved = saved();
+        let data = saved.to_bytes();
+        assert_eq!(data.len(), SAVE_SIZE);
+        assert_eq!(blob_size(&data).unwrap(), SAVE_SIZE);
+        let restored = SavedContext::from_bytes(&data).unwrap();
+        assert_eq!(restored.queue_size, 32);
+        assert_eq!(
+            restored.logical_context.to_bytes(Charset::Wide),
+            saved.logical_context.to_bytes(Charset::Wide)
+        );
+        // trailing bytes past the blob are ignored
+        let mut padded = data.clone();
+        padded.extend_from_slice(&[0xff; 16]);
+        assert!(SavedContext::from_bytes(&padded).is_ok());
+    }
+
+    #[test]
+    fn version_1_is_read_as_ansi() {
+        let saved = saved();
+        let mut data = SAVE_MAGIC.to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&saved.logical_context.to_bytes(Charset::Ansi));
+        data.extend_from_slice(&saved.queue_size.to_le_bytes());
+        data.extend_from_slice(&checksum(&data).to_le_bytes());
+        assert_eq!(data.len(), 188);
+        assert_eq!(blob_size(&data).unwrap(), 188);
+        let restored = SavedContext::from_bytes(&data).unwrap();
+        assert_eq!(restored.logical_context.name, "Saved context");
+        assert_eq!(restored.logical_context.packet_rate, 75);
+        assert_eq!(restored.logical_context.out_ext_y, -1080);
+    }
+
+    #[test]
+    fn rejects_wrong_magic() {
+        let mut data = saved().to_bytes();
+        data[0] = b'X';
+        assert_eq!(error(&data), "not a PSM context save");
+        assert_eq!(error(&data[..4]), "not a PSM context save");
+    }
+
+    #[test]
+    fn rejects_wrong_version() {
+        let mut data = saved().to_bytes();
+        data[4..6].copy_from_slice(&3u16.to_le_bytes());
+        assert!(error(&data).starts_with("unsupported context save version 3"));
+        data[4..6].copy_from_slice(&0u16.to_le_bytes());
+        assert!(error(&data).starts_with("unsupported context save version 0"));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let data = saved().to_bytes();
+        assert_eq!(error(&data[..SAVE_SIZE - 1]), "context save is truncated");
+        assert_eq!(
+            error(&data[..SAVE_HEADER_SIZE]),
+            "context save is truncated"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_queue_size() {
+        for queue_size in [0, MAX_QUEUE_SIZE as u32 + 1] {
+            let data = SavedContext {
+                queue_size,
+                ..saved()
+            }
+            .to_bytes();
+            assert_eq!(
+                error(&data),
+                format!("context save has an invalid queue size {}", queue_size)
+            );
+        }
+        let data = SavedContext {
+            queue_size: MAX_QUEUE_SIZE as u32,
+            ..saved()
+        }
+        .to_bytes();
+        assert!(SavedContext::from_bytes(&data).is_ok());
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let mut data = saved().to_bytes();
+        data[SAVE_HEADER_SIZE + 3] ^= 1;
+        assert_eq!(error(&data), "context save is corrupt (checksum mismatch)");
+        let mut data = saved().to_bytes();
+        data[SAVE_SIZE - 1] ^= 1;
+        assert_eq!(error(&data), "context save is corrupt (checksum mismatch)");
+    }
+}
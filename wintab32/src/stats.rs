@@ -0,0 +1,99 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Live packet statistics, reported through WTI_STATUS.
+pub static STATS: Stats = Stats::new();
+
+/// How often the packet rate is recomputed.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+pub struct Counter(AtomicU32);
+impl Counter {
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Stats {
+    /// Tablet packets received from the client.
+    pub received: Counter,
+    /// Packets queued into a context.
+    pub delivered: Counter,
+    /// Queued packets lost because the context's queue was full.
+    pub dropped_overflow: Counter,
+    /// Packets thrown away because they were outside of the context's output area.
+    pub dropped_out_of_range: Counter,
+    rate: Mutex<RateMeter>,
+}
+
+struct RateMeter {
+    window_start: Option<Instant>,
+    count: u32,
+    /// Packets per second over the last complete window.
+    last_rate: u32,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            received: Counter::new(),
+            delivered: Counter::new(),
+            dropped_overflow: Counter::new(),
+            dropped_out_of_range: Counter::new(),
+            rate: Mutex::new(RateMeter {
+                window_start: None,
+                count: 0,
+                last_rate: 0,
+            }),
+        }
+    }
+
+    /// Counts a delivered packet and updates the packet rate.
+    pub fn packet_delivered(&self) {
+        self.delivered.inc();
+        let mut rate = self.rate.lock().unwrap();
+        let now = Instant::now();
+        let start = *rate.window_start.get_or_insert(now);
+        rate.count += 1;
+        let elapsed = now - start;
+        if elapsed >= RATE_WINDOW {
+            rate.last_rate = (rate.count as f64 / elapsed.as_secs_f64()).round() as u32;
+            rate.count = 0;
+            rate.window_start = Some(now);
+        }
+    }
+
+    /// Packet rate in Hertz, 0 if no packets came in lately.
+    pub fn packet_rate(&self) -> u32 {
+        let rate = self.rate.lock().unwrap();
+        match rate.window_start {
+            Some(start) if start.elapsed() < RATE_WINDOW * 2 => rate.last_rate,
+            _ => 0,
+        }
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,345 @@
+use log::warn;
+use windows::Win32::UI::{
+    Input::KeyboardAndMouse::{
+        INPUT, INPUT_0, INPUT_MOUSE, MOUSE_EVENT_FLAGS, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_LEFTDOWN,
+        MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE,
+        MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK, MOUSEINPUT, SendInput,
+    },
+    WindowsAndMessaging::{
+        GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+        SM_YVIRTUALSCREEN,
+    },
+};
+
+use crate::ffi::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// Mouse button of a system button action, and whether it double clicks.
+/// Clicks and drags both hold the mouse button for as long as the tablet button is down.
+fn system_action(code: u8) -> Option<(MouseButton, bool)> {
+    Some(match code {
+        SBN_LCLICK | SBN_LDRAG => (MouseButton::Left, false),
+        SBN_LDBLCLICK => (MouseButton::Left, true),
+        SBN_RCLICK | SBN_RDRAG => (MouseButton::Right, false),
+        SBN_RDBLCLICK => (MouseButton::Right, true),
+        SBN_MCLICK | SBN_MDRAG => (MouseButton::Middle, false),
+        SBN_MDBLCLICK => (MouseButton::Middle, true),
+        _ => return None,
+    })
+}
+
+/// Where system context packets end up. Implemented by [SendInputSink] on Windows.
+pub trait CursorSink {
+    /// Moves the cursor to a point in screen pixels.
+    fn move_to(&mut self, x: i32, y: i32);
+    /// Moves the cursor by a number of pixels.
+    fn move_by(&mut self, dx: i32, dy: i32);
+    fn button(&mut self, button: MouseButton, down: bool);
+}
+
+/// A [CursorSink] call, recorded by `Vec<CursorEvent>` to be [replay]ed later,
+/// e.g. once the state lock is released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorEvent {
+    MoveTo(i32, i32),
+    MoveBy(i32, i32),
+    Button(MouseButton, bool),
+}
+impl CursorSink for Vec<CursorEvent> {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.push(CursorEvent::MoveTo(x, y));
+    }
+    fn move_by(&mut self, dx: i32, dy: i32) {
+        self.push(CursorEvent::MoveBy(dx, dy));
+    }
+    fn button(&mut self, button: MouseButton, down: bool) {
+        self.push(CursorEvent::Button(button, down));
+    }
+}
+
+/// Sends recorded events to `sink`, in order.
+pub fn replay(events: impl IntoIterator<Item = CursorEvent>, sink: &mut impl CursorSink) {
+    for event in events {
+        match event {
+            CursorEvent::MoveTo(x, y) => sink.move_to(x, y),
+            CursorEvent::MoveBy(dx, dy) => sink.move_by(dx, dy),
+            CursorEvent::Button(button, down) => sink.button(button, down),
+        }
+    }
+}
+
+/// Turns packets of system contexts (CXO_SYSTEM) into cursor movement and clicks.
+#[derive(Default)]
+pub struct SystemCursor {
+    /// Button state of the last packet.
+    buttons: u32,
+    /// Mouse button held down by each logical tablet button.
+    held: [Option<MouseButton>; 32],
+    /// Position of the last packet in context output coordinates, for the relative mode.
+    last: Option<(i32, i32)>,
+}
+impl SystemCursor {
+    /// Moves the cursor to the packet and presses the mouse buttons `system_buttons`
+    /// (CSR_SYSBTNMAP of the packet's cursor) gives to its logical buttons.
+    pub fn update(
+        &mut self,
+        lc: &WtiLogicalContext,
+        packet: &Packet,
+        system_buttons: &[u8; 32],
+        sink: &mut impl CursorSink,
+    ) {
+        let (x, y) = (packet.x as i32, packet.y as i32);
+        // lcSysMode: zero is absolute, anything else is relative
+        if lc.sys_mode == 0 {
+            let (sx, sy) = map_absolute(lc, x, y);
+            sink.move_to(sx, sy);
+        } else if let Some((last_x, last_y)) = self.last {
+            let (dx, dy) = map_relative(lc, x - last_x, y - last_y);
+            if dx != 0 || dy != 0 {
+                sink.move_by(dx, dy);
+            }
+        }
+        self.last = Some((x, y));
+
+        let changed = self.buttons ^ packet.buttons;
+        for i in (0..32).filter(|i| changed & (1 << i) != 0) {
+            if packet.buttons & (1 << i) == 0 {
+                if let Some(button) = self.held[i].take() {
+                    sink.button(button, false);
+                }
+            } else if let Some((button, double)) = system_action(system_buttons[i]) {
+                if double {
+                    sink.button(button, true);
+                    sink.button(button, false);
+                }
+                sink.button(button, true);
+                self.held[i] = Some(button);
+            }
+        }
+        self.buttons = packet.buttons;
+    }
+
+    /// Releases held buttons, e.g. when the pen leaves the context.
+    pub fn reset(&mut self, sink: &mut impl CursorSink) {
+        for button in self.held.iter_mut().filter_map(Option::take) {
+            sink.button(button, false);
+        }
+        self.buttons = 0;
+        self.last = None;
+    }
+}
+
+/// Maps a point from the context's output area onto `lcSysOrg`/`lcSysExt`.
+pub fn map_absolute(lc: &WtiLogicalContext, x: i32, y: i32) -> (i32, i32) {
+    (
+        scale(x - lc.out_org_x, lc.out_ext_x, lc.sys_ext_x) + lc.sys_org_x,
+        scale(y - lc.out_org_y, lc.out_ext_y, lc.sys_ext_y) + lc.sys_org_y,
+    )
+}
+
+/// Maps a movement in output coordinates to pixels, applying `lcSysSens`.
+pub fn map_relative(lc: &WtiLogicalContext, dx: i32, dy: i32) -> (i32, i32) {
+    let sens = |value: i32, sens: i32| ((value as i64 * sens as i64) >> 16) as i32;
+    (
+        sens(scale(dx, lc.out_ext_x, lc.sys_ext_x), lc.sys_sens_x),
+        sens(scale(dy, lc.out_ext_y, lc.sys_ext_y), lc.sys_sens_y),
+    )
+}
+
+/// `value * to / from`, ignoring the extents' signs.
+fn scale(value: i32, from: i32, to: i32) -> i32 {
+    if from == 0 {
+        return 0;
+    }
+    (value as i64 * to.abs() as i64 / from.abs() as i64) as i32
+}
+
+/// Moves the real cursor with SendInput.
+pub struct SendInputSink;
+impl SendInputSink {
+    fn send(&self, dx: i32, dy: i32, flags: MOUSE_EVENT_FLAGS) {
+        let input = INPUT {
+            r#type: INPUT_MOUSE,
+            Anonymous: INPUT_0 {
+                mi: MOUSEINPUT {
+                    dx,
+                    dy,
+                    mouseData: 0,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        };
+        let sent = unsafe { SendInput(&[input], size_of::<INPUT>() as i32) };
+        if sent != 1 {
+            warn!(
+                "SendInput failed to inject mouse input (flags {:#x})",
+                flags.0
+            );
+        }
+    }
+}
+impl CursorSink for SendInputSink {
+    fn move_to(&mut self, x: i32, y: i32) {
+        // absolute input is normalized to 0..=65535 over the virtual desktop
+        let (left, top, width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            )
+        };
+        let normalize = |value: i32, origin: i32, size: i32| {
+            ((value - origin) as i64 * 65535 / (size - 1).max(1) as i64).clamp(0, 65535) as i32
+        };
+        self.send(
+            normalize(x, left, width),
+            normalize(y, top, height),
+            MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
+        );
+    }
+
+    fn move_by(&mut self, dx: i32, dy: i32) {
+        self.send(dx, dy, MOUSEEVENTF_MOVE);
+    }
+
+    fn button(&mut self, button: MouseButton, down: bool) {
+        let flags = match (button, down) {
+            (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+            (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+            (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+            (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+            (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+            (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+        };
+        self.send(0, 0, flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> WtiLogicalContext {
+        let mut lc = WtiLogicalContext::psm_default();
+        lc.out_org_x = 0;
+        lc.out_org_y = 0;
+        lc.out_ext_x = 1000;
+        lc.out_ext_y = 500;
+        lc.sys_org_x = 100;
+        lc.sys_org_y = 50;
+        lc.sys_ext_x = 2000;
+        lc.sys_ext_y = 1000;
+        lc
+    }
+
+    /// Left, right and middle click on the first three buttons, like the default config.
+    const SYSTEM_BUTTONS: [u8; 32] = {
+        let mut map = [SBN_NONE; 32];
+        map[0] = SBN_LCLICK;
+        map[1] = SBN_RCLICK;
+        map[2] = SBN_MCLICK;
+        map
+    };
+
+    fn packet(x: u32, y: u32, buttons: u32) -> Packet {
+        Packet {
+            x,
+            y,
+            buttons,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn absolute_mode_maps_onto_sys_area() {
+        let lc = context();
+        let mut cursor = SystemCursor::default();
+        let mut sink = Vec::new();
+        cursor.update(&lc, &packet(0, 0, 0), &SYSTEM_BUTTONS, &mut sink);
+        cursor.update(&lc, &packet(500, 250, 0), &SYSTEM_BUTTONS, &mut sink);
+        cursor.update(&lc, &packet(1000, 500, 0), &SYSTEM_BUTTONS, &mut sink);
+        assert_eq!(
+            sink,
+            [
+                CursorEvent::MoveTo(100, 50),
+                CursorEvent::MoveTo(1100, 550),
+                CursorEvent::MoveTo(2100, 1050),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_mode_applies_sensitivity() {
+        let mut lc = context();
+        lc.sys_mode = 1;
+        lc.sys_sens_x = 0x0002_0000;
+        lc.sys_sens_y = 0x0000_8000;
+        let mut cursor = SystemCursor::default();
+        let mut sink = Vec::new();
+        cursor.update(&lc, &packet(100, 100, 0), &SYSTEM_BUTTONS, &mut sink);
+        cursor.update(&lc, &packet(110, 90, 0), &SYSTEM_BUTTONS, &mut sink);
+        cursor.update(&lc, &packet(110, 90, 0), &SYSTEM_BUTTONS, &mut sink);
+        assert_eq!(sink, [CursorEvent::MoveBy(40, -10)]);
+    }
+
+    #[test]
+    fn buttons_click_on_transitions() {
+        let lc = context();
+        let mut cursor = SystemCursor::default();
+        let mut sink = Vec::new();
+        cursor.update(&lc, &packet(0, 0, 0b001), &SYSTEM_BUTTONS, &mut sink);
+        cursor.update(&lc, &packet(0, 0, 0b011), &SYSTEM_BUTTONS, &mut sink);
+        cursor.update(&lc, &packet(0, 0, 0b010), &SYSTEM_BUTTONS, &mut sink);
+        cursor.reset(&mut sink);
+        let buttons = sink
+            .into_iter()
+            .filter(|x| matches!(x, CursorEvent::Button(..)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            buttons,
+            [
+                CursorEvent::Button(MouseButton::Left, true),
+                CursorEvent::Button(MouseButton::Right, true),
+                CursorEvent::Button(MouseButton::Left, false),
+                CursorEvent::Button(MouseButton::Right, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_follow_system_map() {
+        let lc = context();
+        let mut map = [SBN_NONE; 32];
+        map[0] = SBN_RDBLCLICK;
+        map[2] = SBN_LDRAG;
+        let mut cursor = SystemCursor::default();
+        let mut sink = Vec::new();
+        cursor.update(&lc, &packet(0, 0, 0b011), &map, &mut sink);
+        cursor.update(&lc, &packet(0, 0, 0b100), &map, &mut sink);
+        cursor.reset(&mut sink);
+        let buttons = sink
+            .into_iter()
+            .filter(|x| matches!(x, CursorEvent::Button(..)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            buttons,
+            [
+                CursorEvent::Button(MouseButton::Right, true),
+                CursorEvent::Button(MouseButton::Right, false),
+                CursorEvent::Button(MouseButton::Right, true),
+                CursorEvent::Button(MouseButton::Right, false),
+                CursorEvent::Button(MouseButton::Left, true),
+                CursorEvent::Button(MouseButton::Left, false),
+            ]
+        );
+    }
+}
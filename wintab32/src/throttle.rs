@@ -0,0 +1,261 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ffi::Packet;
+
+/// What happens to packets that come in faster than a context's `lcPktRate`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottleMode {
+    /// Deliver every packet.
+    Off,
+    /// Deliver the latest packet once per period, dropping the ones in between.
+    Drop,
+    /// Like [ThrottleMode::Drop], but the delivered packet carries the peak pressure
+    /// of the packets it replaces.
+    #[default]
+    Coalesce,
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Per-context packet rate limiter.
+///
+/// Button transitions always go through right away and replace the held back packet.
+/// Otherwise a held back packet is delivered with the next one or by [Throttle::take_pending],
+/// since there's no timer to flush it.
+#[derive(Default)]
+pub struct Throttle {
+    last_sent: Option<Instant>,
+    /// Buttons of the last delivered packet.
+    buttons: u32,
+    pending: Option<Packet>,
+}
+impl Throttle {
+    /// Returns the packet to deliver now, if any.
+    pub fn push(
+        &mut self,
+        mut packet: Packet,
+        rate: u32,
+        mode: ThrottleMode,
+        clock: &impl Clock,
+    ) -> Option<Packet> {
+        if mode == ThrottleMode::Off || rate == 0 {
+            return Some(packet);
+        }
+        let now = clock.now();
+        let pending = self.pending.take();
+        if packet.buttons != self.buttons {
+            // the transition replaces the held back packet, with its own pressure
+            self.last_sent = Some(now);
+            self.buttons = packet.buttons;
+            return Some(packet);
+        }
+        if let Some(pending) = pending
+            && mode == ThrottleMode::Coalesce
+        {
+            packet.normal_pressure = packet.normal_pressure.max(pending.normal_pressure);
+            packet.tangential_pressure =
+                packet.tangential_pressure.max(pending.tangential_pressure);
+        }
+        let period = Duration::from_secs(1) / rate;
+        let due = self.last_sent.is_none_or(|x| now - x >= period);
+        if !due {
+            self.pending = Some(packet);
+            return None;
+        }
+        self.last_sent = Some(now);
+        self.buttons = packet.buttons;
+        Some(packet)
+    }
+
+    /// Takes the held back packet, e.g. when the pen leaves proximity.
+    pub fn take_pending(&mut self) -> Option<Packet> {
+        let packet = self.pending.take()?;
+        self.buttons = packet.buttons;
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    struct MockClock(Cell<Instant>);
+    impl MockClock {
+        fn new() -> Self {
+            Self(Cell::new(Instant::now()))
+        }
+        fn advance(&self, ms: u64) {
+            self.0.set(self.0.get() + Duration::from_millis(ms));
+        }
+    }
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    fn packet(x: u32, buttons: u32, normal_pressure: u32) -> Packet {
+        Packet {
+            x,
+            buttons,
+            normal_pressure,
+            ..Default::default()
+        }
+    }
+
+    /// Feeds packets 1 ms apart, like a 1000 Hz client, returning the delivered ones.
+    fn feed(
+        throttle: &mut Throttle,
+        clock: &MockClock,
+        mode: ThrottleMode,
+        packets: Vec<Packet>,
+    ) -> Vec<Packet> {
+        let mut delivered = Vec::new();
+        for packet in packets {
+            delivered.extend(throttle.push(packet, 100, mode, clock));
+            clock.advance(1);
+        }
+        delivered
+    }
+
+    #[test]
+    fn limits_to_packet_rate() {
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let packets = (0..100).map(|x| packet(x, 0, 0)).collect();
+        let delivered = feed(&mut throttle, &clock, ThrottleMode::Coalesce, packets);
+        // 100 ms at 100 Hz
+        assert_eq!(delivered.len(), 10);
+        assert_eq!(
+            delivered.iter().map(|x| x.x).collect::<Vec<_>>(),
+            [0, 10, 20, 30, 40, 50, 60, 70, 80, 90]
+        );
+    }
+
+    #[test]
+    fn off_and_zero_rate_pass_everything() {
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let packets = (0..20).map(|x| packet(x, 0, 0)).collect();
+        assert_eq!(
+            feed(&mut throttle, &clock, ThrottleMode::Off, packets).len(),
+            20
+        );
+        for i in 0..20 {
+            assert!(
+                throttle
+                    .push(packet(i, 0, 0), 0, ThrottleMode::Coalesce, &clock)
+                    .is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn button_transitions_go_through() {
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let packets = vec![
+            packet(0, 0, 0),
+            packet(1, 1, 100),
+            packet(2, 1, 200),
+            packet(3, 0, 0),
+            packet(4, 0, 0),
+        ];
+        let delivered = feed(&mut throttle, &clock, ThrottleMode::Drop, packets);
+        assert_eq!(
+            delivered
+                .iter()
+                .map(|x| (x.x, x.buttons))
+                .collect::<Vec<_>>(),
+            [(0, 0), (1, 1), (3, 0)]
+        );
+    }
+
+    #[test]
+    fn coalesced_pressure_stays_before_transitions() {
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let packets = vec![
+            packet(0, 1, 100),
+            packet(1, 1, 900),
+            packet(2, 1, 500),
+            packet(3, 0, 0),
+            packet(4, 0, 0),
+        ];
+        let delivered = feed(&mut throttle, &clock, ThrottleMode::Coalesce, packets);
+        // pen-up goes out right away with its own pressure
+        assert_eq!(
+            delivered
+                .iter()
+                .map(|x| (x.x, x.buttons, x.normal_pressure))
+                .collect::<Vec<_>>(),
+            [(0, 1, 100), (3, 0, 0)]
+        );
+        assert!(
+            throttle
+                .take_pending()
+                .is_some_and(|x| x.normal_pressure == 0)
+        );
+    }
+
+    #[test]
+    fn coalescing_keeps_peak_pressure() {
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let pressures = [10, 10, 50, 900, 300, 20, 20, 20, 20, 20, 15];
+        let packets = pressures
+            .iter()
+            .enumerate()
+            .map(|(i, x)| packet(i as u32, 1, *x))
+            .collect::<Vec<_>>();
+        let delivered = feed(
+            &mut throttle,
+            &clock,
+            ThrottleMode::Coalesce,
+            packets.clone(),
+        );
+        assert_eq!(
+            delivered
+                .iter()
+                .map(|x| (x.x, x.normal_pressure))
+                .collect::<Vec<_>>(),
+            [(0, 10), (10, 900)]
+        );
+
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let delivered = feed(&mut throttle, &clock, ThrottleMode::Drop, packets);
+        assert_eq!(
+            delivered
+                .iter()
+                .map(|x| (x.x, x.normal_pressure))
+                .collect::<Vec<_>>(),
+            [(0, 10), (10, 15)]
+        );
+    }
+
+    #[test]
+    fn pending_packet_can_be_flushed() {
+        let clock = MockClock::new();
+        let mut throttle = Throttle::default();
+        let packets = vec![packet(0, 0, 0), packet(1, 0, 5), packet(2, 0, 3)];
+        feed(&mut throttle, &clock, ThrottleMode::Coalesce, packets);
+        let pending = throttle.take_pending().unwrap();
+        assert_eq!((pending.x, pending.normal_pressure), (2, 5));
+        assert!(throttle.take_pending().is_none());
+    }
+}
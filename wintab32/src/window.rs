@@ -0,0 +1,60 @@
+#[cfg(test)]
+use std::cell::RefCell;
+
+#[cfg(not(test))]
+use windows::Win32::UI::WindowsAndMessaging::PostMessageW;
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, WPARAM},
+    UI::WindowsAndMessaging::IsWindow,
+};
+
+/// Tells whether a context's owner window still exists.
+pub trait WindowTracker {
+    fn is_alive(&self, hwnd: HWND) -> bool;
+}
+
+/// Asks Windows with IsWindow.
+pub struct Win32Windows;
+impl WindowTracker for Win32Windows {
+    fn is_alive(&self, hwnd: HWND) -> bool {
+        unsafe { IsWindow(Some(hwnd)).as_bool() }
+    }
+}
+
+/// A message posted to a window: (window, message, wParam, lParam).
+pub type PostedMessage = (HWND, u32, WPARAM, LPARAM);
+
+#[cfg(test)]
+thread_local! {
+    static POSTED: RefCell<Vec<PostedMessage>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Posts a message to the window's queue with PostMessageW.
+#[cfg(not(test))]
+pub fn post_message(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> color_eyre::Result<()> {
+    unsafe { PostMessageW(Some(hwnd), message, wparam, lparam)? };
+    Ok(())
+}
+
+/// Records the message instead of posting it, see [take_posted].
+#[cfg(test)]
+pub fn post_message(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> color_eyre::Result<()> {
+    POSTED.with_borrow_mut(|x| x.push((hwnd, message, wparam, lparam)));
+    Ok(())
+}
+
+/// Messages posted from this thread since the last call.
+#[cfg(test)]
+pub fn take_posted() -> Vec<PostedMessage> {
+    POSTED.take()
+}
@@ -0,0 +1,106 @@
+//! Checks the export table of the built wintab32.dll, which is what apps importing by ordinal see.
+
+use std::path::PathBuf;
+
+use object::{
+    pe::{ImageNtHeaders32, ImageNtHeaders64},
+    read::pe::{ImageNtHeaders, PeFile},
+};
+
+/// Every ORD_* in wintab.h.
+const WINTAB_ORDINALS: [(&str, u32); 49] = [
+    ("WTInfoA", 20),
+    ("WTOpenA", 21),
+    ("WTClose", 22),
+    ("WTPacketsGet", 23),
+    ("WTPacket", 24),
+    ("WTEnable", 40),
+    ("WTOverlap", 41),
+    ("WTConfig", 60),
+    ("WTGetA", 61),
+    ("WTSetA", 62),
+    ("WTExtGet", 63),
+    ("WTExtSet", 64),
+    ("WTSave", 65),
+    ("WTRestore", 66),
+    ("WTPacketsPeek", 80),
+    ("WTDataGet", 81),
+    ("WTDataPeek", 82),
+    ("WTQueueSizeGet", 84),
+    ("WTQueueSizeSet", 85),
+    ("WTMgrOpen", 100),
+    ("WTMgrClose", 101),
+    ("WTMgrContextEnum", 120),
+    ("WTMgrContextOwner", 121),
+    ("WTMgrDefContext", 122),
+    ("WTMgrDeviceConfig", 140),
+    ("WTMgrConfigReplaceA", 141),
+    ("WTMgrPacketHookA", 160),
+    ("WTMgrPacketHookDefProc", 161),
+    ("WTMgrExt", 180),
+    ("WTMgrCsrEnable", 181),
+    ("WTMgrCsrButtonMap", 182),
+    ("WTMgrCsrPressureBtnMarks", 183),
+    ("WTMgrCsrPressureResponse", 184),
+    ("WTMgrCsrExt", 185),
+    ("WTQueuePacketsEx", 200),
+    ("WTMgrCsrPressureBtnMarksEx", 201),
+    ("WTMgrConfigReplaceExA", 202),
+    ("WTMgrPacketHookExA", 203),
+    ("WTMgrPacketUnhook", 204),
+    ("WTMgrPacketHookNext", 205),
+    ("WTMgrDefContextEx", 206),
+    ("WTInfoW", 1020),
+    ("WTOpenW", 1021),
+    ("WTGetW", 1061),
+    ("WTSetW", 1062),
+    ("WTMgrConfigReplaceW", 1141),
+    ("WTMgrPacketHookW", 1160),
+    ("WTMgrConfigReplaceExW", 1202),
+    ("WTMgrPacketHookExW", 1203),
+];
+
+/// Names PSM exports on top of the original DLL, for apps built against the unsuffixed names.
+const EXTRA_EXPORTS: [&str; 5] = ["WTInfo", "WTOpen", "WTGet", "WTSet", "WTMgrPacketHookEx"];
+
+/// The cdylib cargo built next to this test's deps directory.
+fn dll() -> Vec<u8> {
+    let exe = std::env::current_exe().unwrap();
+    let path: PathBuf = exe.parent().unwrap().parent().unwrap().join("wintab32.dll");
+    std::fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+/// (name, ordinal) of every named export.
+fn exports<Pe: ImageNtHeaders>(data: &[u8]) -> Vec<(String, u32)> {
+    let file = PeFile::<Pe>::parse(data).unwrap();
+    let table = file.export_table().unwrap().expect("no export table");
+    table
+        .exports()
+        .unwrap()
+        .into_iter()
+        .filter_map(|x| Some((String::from_utf8(x.name?.to_vec()).unwrap(), x.ordinal)))
+        .collect()
+}
+
+/// rustc links cdylibs with a .def of its own listing every `no_mangle` export,
+/// so this also shows that the one from build.rs wins over it.
+#[test]
+fn export_table_pins_wintab_ordinals() {
+    let data = dll();
+    let exports = if cfg!(target_pointer_width = "64") {
+        exports::<ImageNtHeaders64>(&data)
+    } else {
+        exports::<ImageNtHeaders32>(&data)
+    };
+    for (name, ordinal) in WINTAB_ORDINALS {
+        let found = exports.iter().find(|x| x.0 == name).map(|x| x.1);
+        assert_eq!(found, Some(ordinal), "{name}");
+    }
+    // the extra names take ordinals the original DLL doesn't use
+    for name in EXTRA_EXPORTS {
+        let ordinal = exports.iter().find(|x| x.0 == name).map(|x| x.1);
+        let ordinal = ordinal.unwrap_or_else(|| panic!("{name} isn't exported"));
+        assert!(WINTAB_ORDINALS.iter().all(|x| x.1 != ordinal), "{name}");
+    }
+    assert_eq!(exports.len(), WINTAB_ORDINALS.len() + EXTRA_EXPORTS.len());
+}